@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    AppType,
+    DataType,
+    Error,
+    Flags,
+    FlashSize,
+    PARTITION_TABLE_SIZE,
+    Partition,
+    PartitionTable,
+    SubType,
+    Type,
+    ValidationContext,
+    partition::OTADATA_SIZE,
+};
+
+const PHY_INIT_SIZE: u32 = 0x1000;
+const MAX_OTA_PARTITIONS: u8 = 16;
+
+/// Convenience methods for expressing partition sizes
+///
+/// ```rust
+/// use esp_idf_part::SizeExt as _;
+///
+/// assert_eq!(24.kib(), 0x6000);
+/// assert_eq!(1.mib(), 0x100000);
+/// ```
+pub trait SizeExt {
+    /// Interpret the value as a number of kibibytes, returning the size in
+    /// bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes does not fit in a `u32`, i.e. is 4GB or
+    /// more.
+    fn kib(self) -> u32;
+
+    /// Interpret the value as a number of mebibytes, returning the size in
+    /// bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes does not fit in a `u32`, i.e. is 4GB or
+    /// more.
+    fn mib(self) -> u32;
+}
+
+impl SizeExt for u32 {
+    fn kib(self) -> u32 {
+        self.checked_mul(1024)
+            .expect("size in bytes must be less than 4GB")
+    }
+
+    fn mib(self) -> u32 {
+        self.checked_mul(1024 * 1024)
+            .expect("size in bytes must be less than 4GB")
+    }
+}
+
+/// The size of a partition added to a [PartitionTableBuilder]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Size {
+    /// A fixed number of bytes
+    Fixed(u32),
+    /// All space not occupied by other partitions
+    ///
+    /// At most one partition may fill the remaining space, and a flash size
+    /// must be configured.
+    Fill,
+}
+
+impl From<u32> for Size {
+    fn from(size: u32) -> Self {
+        Size::Fixed(size)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    ty: Type,
+    subtype: SubType,
+    offset: Option<u32>,
+    size: Size,
+    flags: Flags,
+    metadata: BTreeMap<String, String>,
+}
+
+/// Build a [PartitionTable] in code, without computing offsets by hand
+///
+/// Partitions are laid out in the order they are added, each placed at the
+/// lowest offset following the previous partition which satisfies the
+/// alignment requirements of its type. The first partition follows the
+/// partition table, whose offset is taken from the [ValidationContext].
+///
+/// ```rust
+/// use esp_idf_part::{DataType, FlashSize, PartitionTableBuilder, Size::Fill, SizeExt as _};
+///
+/// let table = PartitionTableBuilder::new()
+///     .with_flash_size(FlashSize::_4Mb)
+///     .nvs("nvs", 24.kib())
+///     .otadata()
+///     .phy_init()
+///     .app_ota(2, 1.mib())
+///     .data("storage", DataType::Fat, Fill)
+///     .build()
+///     .unwrap();
+///
+/// let storage = table.find("storage").unwrap();
+/// assert_eq!(storage.offset(), 0x220000);
+/// assert_eq!(storage.size(), 0x1E0000);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PartitionTableBuilder {
+    ctx: ValidationContext,
+    entries: Vec<Entry>,
+    // An unsupported number of OTA app partitions requested, which is reported
+    // by `build`
+    invalid_ota_count: Option<u8>,
+}
+
+impl PartitionTableBuilder {
+    /// Construct a new, empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the given context to lay out and validate the partition table
+    pub fn with_context(mut self, ctx: ValidationContext) -> Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// Set the offset of the partition table, see
+    /// [ValidationContext::with_table_offset]
+    pub fn with_table_offset(mut self, offset: u32) -> Self {
+        self.ctx = self.ctx.with_table_offset(offset);
+        self
+    }
+
+    /// Set the size of the flash, see [ValidationContext::with_flash_size]
+    pub fn with_flash_size(mut self, flash_size: FlashSize) -> Self {
+        self.ctx = self.ctx.with_flash_size(flash_size);
+        self
+    }
+
+    /// Add a partition
+    ///
+    /// The partition is placed at its own offset, unless this is `0`, in which
+    /// case it is placed automatically.
+    pub fn partition(mut self, partition: Partition) -> Self {
+        self.entries.push(Entry {
+            name: partition.name(),
+            ty: partition.ty(),
+            subtype: partition.subtype(),
+            offset: Some(partition.offset()).filter(|&offset| offset != 0),
+            size: Size::Fixed(partition.size()),
+            flags: partition.flags(),
+            metadata: partition.metadata().clone(),
+        });
+        self
+    }
+
+    /// Add an `app` partition
+    pub fn app<S, Z>(self, name: S, subtype: AppType, size: Z) -> Self
+    where
+        S: Into<String>,
+        Z: Into<Size>,
+    {
+        self.entry(name.into(), Type::App, SubType::App(subtype), size.into())
+    }
+
+    /// Add a `data` partition
+    pub fn data<S, Z>(self, name: S, subtype: DataType, size: Z) -> Self
+    where
+        S: Into<String>,
+        Z: Into<Size>,
+    {
+        self.entry(name.into(), Type::Data, SubType::Data(subtype), size.into())
+    }
+
+    /// Add an `nvs` data partition
+    pub fn nvs<S, Z>(self, name: S, size: Z) -> Self
+    where
+        S: Into<String>,
+        Z: Into<Size>,
+    {
+        self.data(name, DataType::Nvs, size)
+    }
+
+    /// Add the `otadata` partition, which is always 8kB
+    pub fn otadata(self) -> Self {
+        self.data("otadata", DataType::Ota, OTADATA_SIZE)
+    }
+
+    /// Add the `phy_init` partition, which is always 4kB
+    pub fn phy_init(self) -> Self {
+        self.data("phy_init", DataType::Phy, PHY_INIT_SIZE)
+    }
+
+    /// Add the `factory` app partition
+    pub fn factory<Z>(self, size: Z) -> Self
+    where
+        Z: Into<Size>,
+    {
+        self.app("factory", AppType::Factory, size)
+    }
+
+    /// Add `count` OTA app partitions of equal size, named `ota_0` onwards
+    ///
+    /// ESP-IDF supports at most 16 OTA app partitions; if `count` is greater,
+    /// no partitions are added and [PartitionTableBuilder::build] returns an
+    /// error.
+    pub fn app_ota<Z>(mut self, count: u8, size: Z) -> Self
+    where
+        Z: Into<Size>,
+    {
+        if count > MAX_OTA_PARTITIONS {
+            self.invalid_ota_count = Some(count);
+            return self;
+        }
+
+        let size = size.into();
+        for slot in 0..count {
+            let subtype = AppType::from_repr(AppType::Ota_0 as usize + usize::from(slot)).unwrap();
+            self = self.app(format!("ota_{slot}"), subtype, size);
+        }
+
+        self
+    }
+
+    /// Lay out the partitions and validate the resulting partition table
+    pub fn build(&self) -> Result<PartitionTable, Error> {
+        let unsatisfiable = |reason: String| Err(Error::UnsatisfiableLayout(reason));
+
+        if let Some(count) = self.invalid_ota_count {
+            return unsatisfiable(format!(
+                "{count} OTA app partitions were requested, but at most {MAX_OTA_PARTITIONS} are supported"
+            ));
+        }
+
+        let mut fill = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.size == Size::Fill);
+        let fill_index = fill.next().map(|(index, _)| index);
+        if let Some((_, entry)) = fill.next() {
+            return unsatisfiable(format!(
+                "only one partition may fill the remaining space, but '{}' and '{}' both do",
+                self.entries[fill_index.unwrap()].name,
+                entry.name
+            ));
+        }
+
+        let fixed_size = |entry: &Entry| match entry.size {
+            Size::Fixed(size) => u64::from(size),
+            Size::Fill => 0,
+        };
+        let placed = |entry: &Entry, offset: u64| offset.next_multiple_of(entry.alignment());
+
+        let mut offsets = vec![0; self.entries.len()];
+        let mut sizes = self.entries.iter().map(fixed_size).collect::<Vec<_>>();
+
+        // Place the partitions preceding any fill partition in order, following the
+        // partition table
+        let head = fill_index.unwrap_or(self.entries.len());
+        let mut next = u64::from(self.ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+        for (index, entry) in self.entries[..head].iter().enumerate() {
+            offsets[index] = entry.offset.map_or_else(|| placed(entry, next), u64::from);
+            next = offsets[index] + sizes[index];
+        }
+
+        // Place the partitions following the fill partition backwards from the end of
+        // flash, then give the fill partition the space between
+        if let Some(fill_index) = fill_index {
+            let fill = &self.entries[fill_index];
+            let Some(flash_size) = self.ctx.flash_size() else {
+                return unsatisfiable(format!(
+                    "partition '{}' fills the remaining space, but no flash size was given",
+                    fill.name
+                ));
+            };
+
+            let mut end = u64::from(flash_size.bytes());
+            for (index, entry) in self.entries.iter().enumerate().skip(fill_index + 1).rev() {
+                offsets[index] = match entry.offset {
+                    Some(offset) => u64::from(offset),
+                    None => match end.checked_sub(sizes[index]) {
+                        Some(offset) => offset - offset % entry.alignment(),
+                        None => {
+                            return unsatisfiable(format!(
+                                "partition '{}' does not fit in {flash_size} of flash",
+                                entry.name
+                            ));
+                        }
+                    },
+                };
+                end = offsets[index];
+            }
+
+            offsets[fill_index] = fill.offset.map_or_else(|| placed(fill, next), u64::from);
+            if end <= offsets[fill_index] {
+                return unsatisfiable(format!(
+                    "no space remains in {flash_size} of flash for partition '{}'",
+                    fill.name
+                ));
+            }
+            sizes[fill_index] = end - offsets[fill_index];
+        }
+
+        let mut partitions = Vec::with_capacity(self.entries.len());
+        for ((entry, offset), size) in self.entries.iter().zip(offsets).zip(sizes) {
+            let (Ok(offset), Ok(size)) = (u32::try_from(offset), u32::try_from(size)) else {
+                return Err(Error::PartitionOutOfBounds {
+                    name: entry.name.clone(),
+                    end: offset + size,
+                    limit: 1 << 32,
+                });
+            };
+
+            let mut partition = Partition::new(
+                entry.name.clone(),
+                entry.ty,
+                entry.subtype,
+                offset,
+                size,
+                entry.flags,
+            );
+            partition.set_metadata(entry.metadata.clone());
+            partitions.push(partition);
+        }
+
+        let table = PartitionTable::new(partitions);
+        table.validate_with(&self.ctx)?;
+
+        Ok(table)
+    }
+
+    fn entry(mut self, name: String, ty: Type, subtype: SubType, size: Size) -> Self {
+        self.entries.push(Entry {
+            name,
+            ty,
+            subtype,
+            offset: None,
+            size,
+            flags: Flags::empty(),
+            metadata: BTreeMap::new(),
+        });
+        self
+    }
+}
+
+impl Entry {
+    fn alignment(&self) -> u64 {
+        u64::from(self.ty.alignment())
+    }
+}
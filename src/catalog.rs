@@ -0,0 +1,402 @@
+//! Well-known partition schemes used by community frameworks
+//!
+//! This module is only available when the `catalog` feature is enabled.
+//!
+//! Besides ESP-IDF's own [presets], boards are frequently flashed with the
+//! partition schemes of frameworks such as Arduino-ESP32, CircuitPython,
+//! Tasmota and ESPHome. [identify] can be used to recognize a partition table
+//! read back from a device as one of these schemes.
+//!
+//! ```rust
+//! use esp_idf_part::{
+//!     FlashSize,
+//!     catalog::{self, Framework},
+//! };
+//!
+//! let scheme = catalog::find(Framework::Arduino, "huge_app").unwrap();
+//! assert_eq!(scheme.flash_size(), FlashSize::_4Mb);
+//!
+//! let table = scheme.table().unwrap();
+//! assert_eq!(catalog::identify(&table), Some(scheme));
+//! ```
+//!
+//! [presets]: crate::presets
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::{Error, FlashSize, PartitionTable, SubType, Type};
+
+/// A framework which ships its own partition schemes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Display, Serialize)]
+pub enum Framework {
+    /// [Arduino-ESP32](https://github.com/espressif/arduino-esp32/tree/master/tools/partitions)
+    Arduino,
+    /// [CircuitPython](https://github.com/adafruit/circuitpython/tree/main/ports/espressif/esp-idf-config)
+    CircuitPython,
+    /// [ESPHome](https://github.com/esphome/esphome/blob/dev/esphome/components/esp32/__init__.py)
+    #[strum(serialize = "ESPHome")]
+    Esphome,
+    /// [Tasmota](https://github.com/arendst/Tasmota/tree/development/partitions)
+    Tasmota,
+}
+
+/// A well-known partition scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Scheme {
+    framework: Framework,
+    name: &'static str,
+    flash_size: FlashSize,
+    csv: &'static str,
+}
+
+impl Scheme {
+    const fn new(
+        framework: Framework,
+        name: &'static str,
+        flash_size: FlashSize,
+        csv: &'static str,
+    ) -> Self {
+        Self {
+            framework,
+            name,
+            flash_size,
+            csv,
+        }
+    }
+
+    /// Return the framework which the scheme belongs to
+    pub fn framework(&self) -> Framework {
+        self.framework
+    }
+
+    /// Return the name the framework uses for the scheme
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the flash size the scheme is intended for
+    pub fn flash_size(&self) -> FlashSize {
+        self.flash_size
+    }
+
+    /// Return the scheme's partition table in CSV format
+    pub fn csv(&self) -> &'static str {
+        self.csv
+    }
+
+    /// Construct the scheme's partition table
+    pub fn table(&self) -> Result<PartitionTable, Error> {
+        PartitionTable::try_from_str(self.csv)
+    }
+
+    /// Does the partition table have the same layout as this scheme?
+    ///
+    /// Partition names and flags are ignored, as are numerically given
+    /// subtypes which have a name.
+    pub fn matches(&self, table: &PartitionTable) -> bool {
+        let Ok(scheme) = self.table() else {
+            return false;
+        };
+
+        layout(&scheme) == layout(table)
+    }
+}
+
+impl core::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{} '{}' ({})",
+            self.framework, self.name, self.flash_size
+        )
+    }
+}
+
+/// Return all of the schemes in the catalog
+pub fn schemes() -> &'static [Scheme] {
+    SCHEMES
+}
+
+/// Find a scheme by its framework and name
+pub fn find(framework: Framework, name: &str) -> Option<Scheme> {
+    SCHEMES
+        .iter()
+        .find(|s| s.framework == framework && s.name == name)
+        .copied()
+}
+
+/// Identify the known scheme, if any, which the partition table matches
+///
+/// See [Scheme::matches] for how tables are compared.
+pub fn identify(table: &PartitionTable) -> Option<Scheme> {
+    SCHEMES.iter().find(|s| s.matches(table)).copied()
+}
+
+// The partitions of the table sorted by offset, reduced to the fields which
+// determine its layout
+fn layout(table: &PartitionTable) -> Vec<(Type, SubType, u32, u32)> {
+    let mut layout = table
+        .partitions()
+        .iter()
+        .map(|p| {
+            (
+                p.ty(),
+                p.subtype().canonicalize(p.ty()),
+                p.offset(),
+                p.size(),
+            )
+        })
+        .collect::<Vec<_>>();
+    layout.sort_by_key(|&(_, _, offset, _)| offset);
+
+    layout
+}
+
+const SCHEMES: &[Scheme] = &[
+    Scheme::new(
+        Framework::Arduino,
+        "default",
+        FlashSize::_4Mb,
+        ARDUINO_DEFAULT,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "default_8MB",
+        FlashSize::_8Mb,
+        ARDUINO_DEFAULT_8MB,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "default_16MB",
+        FlashSize::_16Mb,
+        ARDUINO_DEFAULT_16MB,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "huge_app",
+        FlashSize::_4Mb,
+        ARDUINO_HUGE_APP,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "min_spiffs",
+        FlashSize::_4Mb,
+        ARDUINO_MIN_SPIFFS,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "no_ota",
+        FlashSize::_4Mb,
+        ARDUINO_NO_OTA,
+    ),
+    Scheme::new(
+        Framework::Arduino,
+        "noota_3g",
+        FlashSize::_4Mb,
+        ARDUINO_NOOTA_3G,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-2MB-no-uf2",
+        FlashSize::_2Mb,
+        CIRCUITPYTHON_2MB_NO_UF2,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-4MB",
+        FlashSize::_4Mb,
+        CIRCUITPYTHON_4MB,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-4MB-no-uf2",
+        FlashSize::_4Mb,
+        CIRCUITPYTHON_4MB_NO_UF2,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-8MB",
+        FlashSize::_8Mb,
+        CIRCUITPYTHON_8MB,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-8MB-no-uf2",
+        FlashSize::_8Mb,
+        CIRCUITPYTHON_8MB_NO_UF2,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-16MB",
+        FlashSize::_16Mb,
+        CIRCUITPYTHON_16MB,
+    ),
+    Scheme::new(
+        Framework::CircuitPython,
+        "partitions-16MB-no-uf2",
+        FlashSize::_16Mb,
+        CIRCUITPYTHON_16MB_NO_UF2,
+    ),
+    Scheme::new(
+        Framework::Esphome,
+        "default",
+        FlashSize::_4Mb,
+        ESPHOME_DEFAULT,
+    ),
+    Scheme::new(
+        Framework::Tasmota,
+        "safeboot",
+        FlashSize::_4Mb,
+        TASMOTA_SAFEBOOT,
+    ),
+];
+
+const ARDUINO_DEFAULT: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x140000,
+app1,     app,  ota_1,   0x150000,0x140000,
+spiffs,   data, spiffs,  0x290000,0x160000,
+coredump, data, coredump,0x3F0000,0x10000,
+";
+
+const ARDUINO_DEFAULT_8MB: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x330000,
+app1,     app,  ota_1,   0x340000,0x330000,
+spiffs,   data, spiffs,  0x670000,0x180000,
+coredump, data, coredump,0x7F0000,0x10000,
+";
+
+const ARDUINO_DEFAULT_16MB: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x640000,
+app1,     app,  ota_1,   0x650000,0x640000,
+spiffs,   data, spiffs,  0xc90000,0x360000,
+coredump, data, coredump,0xFF0000,0x10000,
+";
+
+const ARDUINO_HUGE_APP: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x300000,
+spiffs,   data, spiffs,  0x310000,0xE0000,
+coredump, data, coredump,0x3F0000,0x10000,
+";
+
+const ARDUINO_MIN_SPIFFS: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x1E0000,
+app1,     app,  ota_1,   0x1F0000,0x1E0000,
+spiffs,   data, spiffs,  0x3D0000,0x20000,
+coredump, data, coredump,0x3F0000,0x10000,
+";
+
+const ARDUINO_NO_OTA: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x200000,
+spiffs,   data, spiffs,  0x210000,0x1E0000,
+coredump, data, coredump,0x3F0000,0x10000,
+";
+
+const ARDUINO_NOOTA_3G: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+app0,     app,  ota_0,   0x10000, 0x100000,
+spiffs,   data, spiffs,  0x110000,0x2E0000,
+coredump, data, coredump,0x3F0000,0x10000,
+";
+
+const CIRCUITPYTHON_2MB_NO_UF2: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+app,      app,  factory, 0x10000,  1408K,
+user_fs,  data, fat,    0x170000,  576K,
+";
+
+const CIRCUITPYTHON_4MB: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+otadata,  data, ota,      0xe000,  8K,
+ota_0,    app,  ota_0,   0x10000,  1408K,
+ota_1,    app,  ota_1,  0x170000,  1408K,
+uf2,      app,  factory,0x2d0000,  256K,
+user_fs,  data, fat,    0x310000,  960K,
+";
+
+const CIRCUITPYTHON_4MB_NO_UF2: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+app,      app,  factory, 0x10000,  2048K,
+user_fs,  data, fat,    0x210000,  1984K,
+";
+
+const CIRCUITPYTHON_8MB: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+otadata,  data, ota,      0xe000,  8K,
+ota_0,    app,  ota_0,   0x10000,  2048K,
+ota_1,    app,  ota_1,  0x210000,  2048K,
+uf2,      app,  factory,0x410000,  256K,
+user_fs,  data, fat,    0x450000,  3776K,
+";
+
+const CIRCUITPYTHON_8MB_NO_UF2: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+otadata,  data, ota,      0xe000,  8K,
+ota_0,    app,  ota_0,   0x10000,  2048K,
+ota_1,    app,  ota_1,  0x210000,  2048K,
+user_fs,  data, fat,    0x410000,  4032K,
+";
+
+const CIRCUITPYTHON_16MB: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+otadata,  data, ota,      0xe000,  8K,
+ota_0,    app,  ota_0,   0x10000,  2048K,
+ota_1,    app,  ota_1,  0x210000,  2048K,
+uf2,      app,  factory,0x410000,  256K,
+user_fs,  data, fat,    0x450000,  11968K,
+";
+
+const CIRCUITPYTHON_16MB_NO_UF2: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,      0x9000,  20K,
+otadata,  data, ota,      0xe000,  8K,
+ota_0,    app,  ota_0,   0x10000,  2048K,
+ota_1,    app,  ota_1,  0x210000,  2048K,
+user_fs,  data, fat,    0x410000,  12224K,
+";
+
+// ESPHome generates this table for ESP-IDF builds with 4MB of flash, leaving
+// the offsets to be assigned automatically, following the partition table
+const ESPHOME_DEFAULT: &str = "\
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+app0,     app,  ota_0,   ,        0x1C0000,
+app1,     app,  ota_1,   ,        0x1C0000,
+nvs,      data, nvs,     ,        0x6D000,
+";
+
+// Tasmota's `esp32_partition_app2880k_fs320k.csv`, with a 320K filesystem
+const TASMOTA_SAFEBOOT: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     0x9000,  0x5000,
+otadata,  data, ota,     0xe000,  0x2000,
+safeboot, app,  factory, 0x10000, 0xC0000,
+app0,     app,  ota_0,   0xD0000, 0x2D0000,
+spiffs,   data, spiffs,  0x3A0000,0x50000,
+";
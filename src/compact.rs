@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use crate::{Error, PARTITION_TABLE_SIZE, Partition, PartitionTable, ValidationContext};
+
+impl PartitionTable {
+    /// Remove any gaps between partitions
+    ///
+    /// See [PartitionTable::compact_with] for more information.
+    pub fn compact(&self) -> Result<(PartitionTable, BTreeMap<u32, u32>), Error> {
+        self.compact_with(&ValidationContext::default(), |_| false)
+    }
+
+    /// Remove any gaps between partitions, leaving those for which `is_pinned`
+    /// returns `true` at their current offsets
+    ///
+    /// In order of their offsets, each partition is moved to the lowest offset
+    /// following the previous partition (or the partition table, whose offset
+    /// is taken from the [ValidationContext]) which satisfies the alignment
+    /// requirements of its type. Partitions are never moved to a higher
+    /// offset, nor reordered.
+    ///
+    /// Returns the compacted partition table, and a map from the offset of each
+    /// partition to its new offset. As partitions only move downwards, their
+    /// contents can be migrated by copying each in ascending order of its
+    /// original offset.
+    pub fn compact_with<F>(
+        &self,
+        ctx: &ValidationContext,
+        is_pinned: F,
+    ) -> Result<(PartitionTable, BTreeMap<u32, u32>), Error>
+    where
+        F: Fn(&Partition) -> bool,
+    {
+        let mut table = self.clone();
+        let mut relocations = BTreeMap::new();
+
+        let mut order = (0..table.partitions.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| table.partitions[index].offset());
+
+        let mut next = u64::from(ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+        for index in order {
+            let partition = &mut table.partitions[index];
+            let offset = partition.offset();
+
+            if !is_pinned(partition) {
+                let lowest = next.next_multiple_of(u64::from(partition.ty().alignment()));
+                // The partition never moves upwards, so its new offset always fits
+                partition.set_offset(lowest.min(u64::from(offset)) as u32);
+            }
+
+            relocations.insert(offset, partition.offset());
+            next = next.max(partition.end());
+        }
+
+        table.validate_with(ctx)?;
+
+        Ok((table, relocations))
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Flags, Partition, PartitionTable, SubType, Type};
+
+/// A change to a single field of a partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum FieldChange {
+    /// The partition's type changed
+    Type { old: Type, new: Type },
+    /// The partition's subtype changed
+    SubType { old: SubType, new: SubType },
+    /// The partition's offset changed
+    Offset { old: u32, new: u32 },
+    /// The partition's size changed
+    Size { old: u32, new: u32 },
+    /// The partition's flags changed
+    Flags { old: Flags, new: Flags },
+}
+
+impl core::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            FieldChange::Type { old, new } => write!(f, "type {old} -> {new}"),
+            FieldChange::SubType { old, new } => write!(f, "subtype {old} -> {new}"),
+            FieldChange::Offset { old, new } => write!(f, "offset {old:#x} -> {new:#x}"),
+            FieldChange::Size { old, new } => write!(f, "size {old:#x} -> {new:#x}"),
+            FieldChange::Flags { old, new } => write!(f, "flags '{old}' -> '{new}'"),
+        }
+    }
+}
+
+/// The changes to a partition present in both partition tables
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct PartitionChange {
+    name: String,
+    changes: Vec<FieldChange>,
+}
+
+impl PartitionChange {
+    /// Return the name of the changed partition
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the changes to the partition's fields
+    pub fn changes(&self) -> &[FieldChange] {
+        &self.changes
+    }
+}
+
+/// The differences between two partition tables, with partitions matched by
+/// name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct PartitionTableDiff {
+    added: Vec<Partition>,
+    removed: Vec<Partition>,
+    changed: Vec<PartitionChange>,
+}
+
+impl PartitionTableDiff {
+    /// Return the partitions which are only present in the new partition table
+    pub fn added(&self) -> &[Partition] {
+        &self.added
+    }
+
+    /// Return the partitions which are only present in the old partition table
+    pub fn removed(&self) -> &[Partition] {
+        &self.removed
+    }
+
+    /// Return the changes to partitions present in both partition tables
+    pub fn changed(&self) -> &[PartitionChange] {
+        &self.changed
+    }
+
+    /// Are the partition tables equivalent?
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Render the differences as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// The differences are rendered as text, one partition per line
+impl core::fmt::Display for PartitionTableDiff {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+
+        let describe = |p: &Partition| {
+            format!(
+                "{}, {} at {:#x} ({:#x} bytes)",
+                p.ty(),
+                p.subtype(),
+                p.offset(),
+                p.size()
+            )
+        };
+
+        for partition in &self.removed {
+            writeln!(f, "- {}: {}", partition.name(), describe(partition))?;
+        }
+        for partition in &self.added {
+            writeln!(f, "+ {}: {}", partition.name(), describe(partition))?;
+        }
+        for change in &self.changed {
+            let changes = change
+                .changes
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+
+            writeln!(f, "~ {}: {}", change.name, changes.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartitionTable {
+    /// Compare the partition table with another, matching partitions by name
+    ///
+    /// `self` is treated as the old partition table and `other` as the new.
+    /// Removed and changed partitions are listed in the order of the old
+    /// partition table, and added partitions in that of the new.
+    pub fn diff(&self, other: &PartitionTable) -> PartitionTableDiff {
+        let mut diff = PartitionTableDiff::default();
+
+        for old in &self.partitions {
+            let Some(new) = other.partitions.iter().find(|p| p.name() == old.name()) else {
+                diff.removed.push(old.clone());
+                continue;
+            };
+
+            let mut changes = Vec::new();
+            if old.ty() != new.ty() {
+                changes.push(FieldChange::Type {
+                    old: old.ty(),
+                    new: new.ty(),
+                });
+            }
+            if old.subtype().canonicalize(old.ty()) != new.subtype().canonicalize(new.ty()) {
+                changes.push(FieldChange::SubType {
+                    old: old.subtype(),
+                    new: new.subtype(),
+                });
+            }
+            if old.offset() != new.offset() {
+                changes.push(FieldChange::Offset {
+                    old: old.offset(),
+                    new: new.offset(),
+                });
+            }
+            if old.size() != new.size() {
+                changes.push(FieldChange::Size {
+                    old: old.size(),
+                    new: new.size(),
+                });
+            }
+            if old.flags() != new.flags() {
+                changes.push(FieldChange::Flags {
+                    old: old.flags(),
+                    new: new.flags(),
+                });
+            }
+
+            if !changes.is_empty() {
+                diff.changed.push(PartitionChange {
+                    name: old.name(),
+                    changes,
+                });
+            }
+        }
+
+        diff.added = other
+            .partitions
+            .iter()
+            .filter(|new| self.find(&new.name()).is_none())
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{Error, Flags, Partition, PartitionTable, ValidationContext};
+
+impl PartitionTable {
+    /// Insert a partition into the partition table
+    ///
+    /// See [PartitionTable::insert_with] for more information.
+    pub fn insert(&mut self, partition: Partition) -> Result<(), Error> {
+        self.insert_with(&ValidationContext::default(), partition)
+    }
+
+    /// Insert a partition into the partition table, validating the result
+    /// using the provided [ValidationContext]
+    ///
+    /// The partition is inserted following any partitions at lower offsets,
+    /// so a table which is sorted by offset remains sorted. If the resulting
+    /// table fails validation it is left unmodified, and the error returned.
+    pub fn insert_with(
+        &mut self,
+        ctx: &ValidationContext,
+        partition: Partition,
+    ) -> Result<(), Error> {
+        self.try_edit(ctx, |partitions| {
+            let index = partitions.partition_point(|p| p.offset() <= partition.offset());
+            partitions.insert(index, partition);
+
+            Ok(())
+        })
+    }
+
+    /// Remove the partition with the given name from the partition table,
+    /// returning it
+    ///
+    /// See [PartitionTable::remove_with] for more information.
+    pub fn remove(&mut self, name: &str) -> Result<Partition, Error> {
+        self.remove_with(&ValidationContext::default(), name)
+    }
+
+    /// Remove the partition with the given name from the partition table,
+    /// returning it, and validate the result using the provided
+    /// [ValidationContext]
+    ///
+    /// If the resulting table fails validation (for example, if the last
+    /// `app` partition would be removed) it is left unmodified, and the error
+    /// returned.
+    pub fn remove_with(&mut self, ctx: &ValidationContext, name: &str) -> Result<Partition, Error> {
+        let index = self.position(name)?;
+
+        let mut removed = None;
+        self.try_edit(ctx, |partitions| {
+            removed = Some(partitions.remove(index));
+            Ok(())
+        })?;
+
+        Ok(removed.unwrap())
+    }
+
+    /// Rename the partition with the given name
+    ///
+    /// See [PartitionTable::rename_with] for more information.
+    pub fn rename<S>(&mut self, name: &str, new_name: S) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        self.rename_with(&ValidationContext::default(), name, new_name)
+    }
+
+    /// Rename the partition with the given name, validating the result using
+    /// the provided [ValidationContext]
+    pub fn rename_with<S>(
+        &mut self,
+        ctx: &ValidationContext,
+        name: &str,
+        new_name: S,
+    ) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let new_name = new_name.into();
+        self.try_edit_partition(ctx, name, |p| p.set_name(new_name))
+    }
+
+    /// Change the size of the partition with the given name
+    ///
+    /// See [PartitionTable::resize_with] for more information.
+    pub fn resize(&mut self, name: &str, size: u32) -> Result<(), Error> {
+        self.resize_with(&ValidationContext::default(), name, size)
+    }
+
+    /// Change the size of the partition with the given name, validating the
+    /// result using the provided [ValidationContext]
+    ///
+    /// The partition's offset is unchanged; the new size must not cause it to
+    /// overlap any following partition.
+    pub fn resize_with(
+        &mut self,
+        ctx: &ValidationContext,
+        name: &str,
+        size: u32,
+    ) -> Result<(), Error> {
+        self.try_edit_partition(ctx, name, |p| p.set_size(size))
+    }
+
+    /// Move the partition with the given name to a new offset
+    ///
+    /// See [PartitionTable::move_to_with] for more information.
+    pub fn move_to(&mut self, name: &str, offset: u32) -> Result<(), Error> {
+        self.move_to_with(&ValidationContext::default(), name, offset)
+    }
+
+    /// Move the partition with the given name to a new offset, validating the
+    /// result using the provided [ValidationContext]
+    ///
+    /// Partitions are kept sorted by offset if they were previously.
+    pub fn move_to_with(
+        &mut self,
+        ctx: &ValidationContext,
+        name: &str,
+        offset: u32,
+    ) -> Result<(), Error> {
+        let was_sorted = self.partitions.is_sorted_by_key(|p| p.offset());
+
+        self.try_edit(ctx, |partitions| {
+            let index = partitions
+                .iter()
+                .position(|p| p.name() == name)
+                .ok_or_else(|| Error::PartitionNotFound(name.into()))?;
+            partitions[index].set_offset(offset);
+
+            if was_sorted {
+                partitions.sort_by_key(|p| p.offset());
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Replace the flags of the partition with the given name
+    ///
+    /// See [PartitionTable::set_flags_with] for more information.
+    pub fn set_flags(&mut self, name: &str, flags: Flags) -> Result<(), Error> {
+        self.set_flags_with(&ValidationContext::default(), name, flags)
+    }
+
+    /// Replace the flags of the partition with the given name, validating the
+    /// result using the provided [ValidationContext]
+    pub fn set_flags_with(
+        &mut self,
+        ctx: &ValidationContext,
+        name: &str,
+        flags: Flags,
+    ) -> Result<(), Error> {
+        self.try_edit_partition(ctx, name, |p| p.set_flags(flags))
+    }
+
+    fn position(&self, name: &str) -> Result<usize, Error> {
+        self.partitions
+            .iter()
+            .position(|p| p.name() == name)
+            .ok_or_else(|| Error::PartitionNotFound(name.into()))
+    }
+
+    fn try_edit_partition<F>(
+        &mut self,
+        ctx: &ValidationContext,
+        name: &str,
+        f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Partition),
+    {
+        let index = self.position(name)?;
+
+        self.try_edit(ctx, |partitions| {
+            f(&mut partitions[index]);
+            Ok(())
+        })
+    }
+
+    /// Apply an edit to the partitions, rolling back if the resulting table
+    /// does not pass validation
+    fn try_edit<F>(&mut self, ctx: &ValidationContext, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<Partition>) -> Result<(), Error>,
+    {
+        let original = self.partitions.clone();
+
+        let result = f(&mut self.partitions).and_then(|_| self.validate_with(ctx));
+        if result.is_err() {
+            self.partitions = original;
+        }
+
+        result
+    }
+}
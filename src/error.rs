@@ -1,3 +1,5 @@
+use crate::IdfVersion;
+
 /// Partition table errors
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -12,11 +14,32 @@ pub enum Error {
         computed: Vec<u8>,
     },
 
+    /// The metadata entry cannot be written as a CSV annotation
+    #[error("Invalid metadata '{key}': {reason}")]
+    InvalidMetadata { key: String, reason: String },
+
+    /// The partition table does not describe a valid OTA configuration
+    #[error("Invalid OTA configuration: {0}")]
+    InvalidOtaLayout(String),
+
     /// Partition with type 'data' and subtype 'ota' must have size of 0x2000
     /// (8k) bytes
     #[error("Partition with type 'data' and subtype 'ota' must have size of 0x2000 (8k) bytes")]
     InvalidOtadataPartitionSize,
 
+    /// An overlay cannot be applied to the partition table
+    #[error("Invalid overlay: {0}")]
+    InvalidOverlay(String),
+
+    /// The partition's name cannot be stored in the partition table
+    #[error("Invalid partition name '{name}': {reason}")]
+    InvalidPartitionName { name: String, reason: String },
+
+    /// The partition table layout is incompatible with the selected Secure Boot
+    /// scheme
+    #[error("The partition table layout is incompatible with Secure Boot: {0}")]
+    InvalidSecureBootLayout(String),
+
     /// The length of the binary data is not a multiple of 32
     #[error("The length of the binary data is not a multiple of 32")]
     LengthNotMultipleOf32,
@@ -41,14 +64,59 @@ pub enum Error {
     #[error("Two partitions are overlapping each other: '{0}' and '{1}'")]
     OverlappingPartitions(String, String),
 
+    /// No partition with the given name exists in the partition table
+    #[error("No partition named '{0}' was found in the partition table")]
+    PartitionNotFound(String),
+
+    /// Partition extends beyond the end of the addressable flash
+    #[error("Partition '{name}' ends at {end:#x}, beyond the end of flash at {limit:#x}")]
+    PartitionOutOfBounds { name: String, end: u64, limit: u64 },
+
     /// Partition is above the maximum supported size of 16MB
     #[error("Partition larger than maximum supported size of 16MB: '{0}'")]
     PartitionTooLarge(String),
 
+    /// Partition is below the minimum usable size for its subtype
+    #[error("Partition '{name}' is too small ({size:#x} bytes, minimum is {minimum:#x}): {reason}")]
+    PartitionTooSmall {
+        name: String,
+        size: u32,
+        minimum: u32,
+        reason: String,
+    },
+
+    /// A user-defined validation rule was violated
+    #[error("Rule '{rule}' violated: {message}")]
+    RuleViolation {
+        rule: String,
+        partition: Option<String>,
+        message: String,
+    },
+
+    /// A partition table template could not be rendered
+    #[error("Error in template on line {line}: {message}")]
+    TemplateError { line: usize, message: String },
+
     /// The partition is not correctly aligned
     #[error("The partition is not correctly aligned")]
     UnalignedPartition,
 
+    /// The requested partitions could not be laid out
+    #[error("Unable to lay out the partition table: {0}")]
+    UnsatisfiableLayout(String),
+
+    /// The partition uses a feature which the targeted ESP-IDF version does not
+    /// support
+    #[error(
+        "Partition '{name}' uses {feature}, which requires ESP-IDF {required} or later (targeting {target})"
+    )]
+    UnsupportedByIdfVersion {
+        name: String,
+        feature: String,
+        required: IdfVersion,
+        target: IdfVersion,
+    },
+
     /// An error which originated in the `csv` package
     #[error(transparent)]
     CsvError(#[from] csv::Error),
@@ -61,6 +129,10 @@ pub enum Error {
     #[error(transparent)]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
 
+    /// An error which originated in the `serde_json` package
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+
     /// An error which originated in the `std::io` module
     #[error(transparent)]
     IoError(#[from] std::io::Error),
@@ -0,0 +1,119 @@
+use core::ops::Range;
+
+use crate::{Partition, PartitionTable};
+
+/// A partition containing a flash address, along with the address relative to
+/// the start of the partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location<'a> {
+    partition: &'a Partition,
+    offset: u32,
+}
+
+impl<'a> Location<'a> {
+    /// Return the partition
+    pub fn partition(&self) -> &'a Partition {
+        self.partition
+    }
+
+    /// Return the offset relative to the start of the partition
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+/// An index of the partitions in a [PartitionTable] by address
+///
+/// Building the index sorts the partitions by offset, after which each lookup
+/// is a binary search. When performing many lookups against the same
+/// partition table, build the index once using [PartitionTable::index].
+#[derive(Debug, Clone)]
+pub struct AddressIndex<'a> {
+    partitions: Vec<&'a Partition>,
+    // The greatest end address of any partition up to and including each index,
+    // which allows lookups to remain correct should partitions overlap
+    max_ends: Vec<u64>,
+}
+
+impl<'a> AddressIndex<'a> {
+    /// Build an index of the given partitions
+    pub fn new(table: &'a PartitionTable) -> Self {
+        let mut partitions = table.partitions().iter().collect::<Vec<_>>();
+        partitions.sort_by_key(|p| p.offset());
+
+        let max_ends = partitions
+            .iter()
+            .scan(0, |max_end, p| {
+                *max_end = p.end().max(*max_end);
+                Some(*max_end)
+            })
+            .collect();
+
+        Self {
+            partitions,
+            max_ends,
+        }
+    }
+
+    /// Find the partition containing the given address, if any
+    pub fn partition_at(&self, address: u32) -> Option<Location<'a>> {
+        self.overlapping(u64::from(address), u64::from(address) + 1)
+            .last()
+            .map(|partition| Location {
+                partition,
+                offset: address - partition.offset(),
+            })
+    }
+
+    /// Find all partitions which contain any part of the given range of
+    /// addresses, in order of their offsets
+    ///
+    /// The offset of each [Location] is that of the start of the range within
+    /// the partition, or `0` if the partition starts within the range.
+    pub fn partitions_in_range(&self, range: Range<u32>) -> Vec<Location<'a>> {
+        self.overlapping(u64::from(range.start), u64::from(range.end))
+            .map(|partition| Location {
+                partition,
+                offset: range.start.saturating_sub(partition.offset()),
+            })
+            .collect()
+    }
+
+    fn overlapping(&self, start: u64, end: u64) -> impl Iterator<Item = &'a Partition> {
+        // Only partitions starting before the end of the range, and ending after its
+        // start, can overlap it
+        let last = self
+            .partitions
+            .partition_point(|p| u64::from(p.offset()) < end);
+        let first = self.max_ends[..last].partition_point(|&max_end| max_end <= start);
+
+        self.partitions[first..last]
+            .iter()
+            .copied()
+            .filter(move |p| start < end && p.end() > start)
+    }
+}
+
+impl PartitionTable {
+    /// Build an index of the partitions by address
+    ///
+    /// See [AddressIndex] for more information.
+    pub fn index(&self) -> AddressIndex<'_> {
+        AddressIndex::new(self)
+    }
+
+    /// Find the partition containing the given address, if any
+    ///
+    /// See [AddressIndex::partition_at] for more information.
+    pub fn partition_at(&self, address: u32) -> Option<Location<'_>> {
+        self.index().partition_at(address)
+    }
+
+    /// Find all partitions which contain any part of the given range of
+    /// addresses, in order of their offsets
+    ///
+    /// See [AddressIndex::partitions_in_range] for more information.
+    pub fn partitions_in_range(&self, range: Range<u32>) -> Vec<Location<'_>> {
+        self.index().partitions_in_range(range)
+    }
+}
@@ -7,14 +7,16 @@
 //!
 //! ## Features
 //!
-//! There is currently only a single feature, `std`; this feature is enabled by
-//! default.
+//! The `std` feature is enabled by default.
 //!
 //! The following functionality is unavailable if the `std` feature is disabled:
 //!
 //! - (De)serializing a [PartitionTable] from/to CSV or binary format
 //! - Writing a [Partition] to a CSV or binary writer
 //!
+//! The opt-in `catalog` feature enables the `catalog` module of well-known
+//! partition schemes used by community frameworks such as Arduino-ESP32.
+//!
 //! ## Examples
 //!
 //! ```rust,ignore
@@ -42,16 +44,69 @@ use deku::prelude::DekuContainerRead as _;
 use serde::{Deserialize, Serialize};
 
 pub use self::{
+    builder::{PartitionTableBuilder, Size, SizeExt},
+    diff::{FieldChange, PartitionChange, PartitionTableDiff},
     error::Error,
-    partition::{AppType, DataType, Flags, Partition, SubType, Type},
+    index::{AddressIndex, Location},
+    map::FlashMap,
+    migration::{MigrationReport, MigrationStatus, PartitionMigration},
+    ota::OtaLayout,
+    overlay::{OverlaidPartitionTable, Overlay},
+    partition::{
+        APP_PARTITION_ALIGNMENT,
+        AppType,
+        DATA_PARTITION_ALIGNMENT,
+        DataType,
+        Flags,
+        Partition,
+        SubType,
+        Type,
+    },
+    query::Query,
+    solver::{Extent, LayoutSolver, Requirement},
+    space::Region,
+    target::{Chip, FlashSize, IdfVersion},
+    template::Template,
+    validation::{
+        Lint,
+        Rule,
+        SecureBootScheme,
+        SizeConstraint,
+        ValidationContext,
+        flags_since,
+        subtype_since,
+        type_since,
+    },
+    visualize::Visualization,
 };
 use self::{
     hash_writer::HashWriter,
-    partition::{DeserializedBinPartition, DeserializedCsvPartition},
+    partition::{DeserializedBinPartition, DeserializedCsvPartition, parse_annotations},
 };
 
+mod builder;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+mod compact;
+mod diff;
+mod edit;
 mod error;
+mod index;
+mod map;
+mod migration;
+mod normalize;
+mod ota;
+mod overlay;
 mod partition;
+pub mod presets;
+mod query;
+mod rescale;
+mod solver;
+mod space;
+mod target;
+mod template;
+mod validation;
+mod visualize;
 
 pub(crate) const MD5_NUM_MAGIC_BYTES: usize = 16;
 const MD5_PART_MAGIC_BYTES: [u8; MD5_NUM_MAGIC_BYTES] = [
@@ -59,8 +114,11 @@ const MD5_PART_MAGIC_BYTES: [u8; MD5_NUM_MAGIC_BYTES] = [
 ];
 const PARTITION_SIZE: usize = 32;
 
+pub(crate) const DEFAULT_PARTITION_TABLE_OFFSET: u32 = 0x8000;
+pub(crate) const PARTITION_TABLE_SIZE: u32 = 0x1000;
+
 /// A partition table; a collection of partitions
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PartitionTable {
     partitions: Vec<Partition>,
 }
@@ -80,6 +138,15 @@ impl PartitionTable {
     /// For more information on the partition table format see:
     /// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-guides/partition-tables.html>
     pub fn try_from<D>(data: D) -> Result<Self, Error>
+    where
+        D: Into<Vec<u8>>,
+    {
+        Self::try_from_with(data, &ValidationContext::default())
+    }
+
+    /// Attempt to parse either a binary or CSV partition table from the given
+    /// input, validating it using the provided [ValidationContext].
+    pub fn try_from_with<D>(data: D, ctx: &ValidationContext) -> Result<Self, Error>
     where
         D: Into<Vec<u8>>,
     {
@@ -89,9 +156,9 @@ impl PartitionTable {
         // it will be passed in its _binary_ form. Otherwise, it will be provided as a
         // CSV. A binary partition table starts with 0xAA 0x50 magic bytes.
         if input[..2] == [0xAA, 0x50] {
-            Self::try_from_bytes(&*input)
+            Self::try_from_bytes_with(&*input, ctx)
         } else {
-            Self::try_from_str(String::from_utf8(input)?)
+            Self::try_from_str_with(String::from_utf8(input)?, ctx)
         }
     }
 
@@ -100,6 +167,15 @@ impl PartitionTable {
     /// For more information on the partition table format see:
     /// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-guides/partition-tables.html>
     pub fn try_from_bytes<B>(bytes: B) -> Result<Self, Error>
+    where
+        B: Into<Vec<u8>>,
+    {
+        Self::try_from_bytes_with(bytes, &ValidationContext::default())
+    }
+
+    /// Attempt to parse a binary partition table from the given bytes,
+    /// validating it using the provided [ValidationContext].
+    pub fn try_from_bytes_with<B>(bytes: B, ctx: &ValidationContext) -> Result<Self, Error>
     where
         B: Into<Vec<u8>>,
     {
@@ -114,7 +190,7 @@ impl PartitionTable {
             return Err(Error::LengthNotMultipleOf32);
         }
 
-        let mut ctx = md5::Md5::new();
+        let mut hasher = md5::Md5::new();
 
         let mut partitions = vec![];
         for line in data.chunks_exact(PARTITION_SIZE) {
@@ -122,7 +198,7 @@ impl PartitionTable {
                 // The first 16 bytes are just the marker. The next 16 bytes is
                 // the actual MD5 string.
                 let digest_in_file = &line[16..32];
-                let digest_computed = ctx.clone().finalize();
+                let digest_computed = hasher.clone().finalize();
 
                 if digest_computed.as_slice() != digest_in_file {
                     return Err(Error::InvalidChecksum {
@@ -136,12 +212,12 @@ impl PartitionTable {
                 let partition = Partition::from(partition);
                 partitions.push(partition);
 
-                ctx.update(line);
+                hasher.update(line);
             } else {
                 // We're finished parsing the binary data, time to construct and return the
                 // [PartitionTable].
                 let table = Self::new(partitions);
-                table.validate()?;
+                table.validate_with(ctx)?;
 
                 return Ok(table);
             }
@@ -158,7 +234,23 @@ impl PartitionTable {
     where
         S: Into<String>,
     {
-        let data = string.into();
+        Self::try_from_str_with(string, &ValidationContext::default())
+    }
+
+    /// Attempt to parse a CSV partition table from the given string,
+    /// validating it using the provided [ValidationContext].
+    pub fn try_from_str_with<S>(string: S, ctx: &ValidationContext) -> Result<Self, Error>
+    where
+        S: Into<String>,
+    {
+        let table = Self::parse_csv_with(&string.into(), ctx)?;
+        table.validate_with(ctx)?;
+
+        Ok(table)
+    }
+
+    /// Parse a CSV partition table without validating it
+    pub(crate) fn parse_csv_with(data: &str, ctx: &ValidationContext) -> Result<Self, Error> {
         let mut reader = csv::ReaderBuilder::new()
             .comment(Some(b'#'))
             .flexible(true)
@@ -166,24 +258,27 @@ impl PartitionTable {
             .trim(csv::Trim::All)
             .from_reader(data.as_bytes());
 
-        // Default offset is 0x8000 in ESP-IDF, partition table size is 0x1000
-        let mut offset = 0x9000;
+        // Partitions without an explicit offset are placed immediately following the
+        // partition table
+        let mut offset = u64::from(ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+
+        // Metadata is given by structured comments preceding each row, which the CSV
+        // reader skips
+        let mut annotations = parse_annotations(data).into_iter();
 
         let mut partitions = vec![];
         for record in reader.deserialize() {
             // Since offsets are optional, we need to update the deserialized
             // partition when this field is omitted
             let mut partition: DeserializedCsvPartition = record?;
-            offset = partition.fix_offset(offset);
+            offset = partition.fix_offset(offset)?;
 
-            let partition = Partition::from(partition);
+            let mut partition = Partition::from(partition);
+            partition.set_metadata(annotations.next().unwrap_or_default());
             partitions.push(partition);
         }
 
-        let table = Self::new(partitions);
-        table.validate()?;
-
-        Ok(table)
+        Ok(Self::new(partitions))
     }
 
     /// Return a reference to a vector containing each partition in the
@@ -212,9 +307,8 @@ impl PartitionTable {
     /// Convert a partition table to binary
     pub fn to_bin(&self) -> Result<Vec<u8>, Error> {
         const MAX_PARTITION_LENGTH: usize = 0xC00;
-        const PARTITION_TABLE_SIZE: usize = 0x1000;
 
-        let mut result = Vec::with_capacity(PARTITION_TABLE_SIZE);
+        let mut result = Vec::with_capacity(PARTITION_TABLE_SIZE as usize);
         let mut hasher = HashWriter::new(&mut result);
 
         for partition in &self.partitions {
@@ -242,29 +336,29 @@ impl PartitionTable {
         csv.push_str("# ESP-IDF Partition Table\n");
         csv.push_str("# Name,Type,SubType,Offset,Size,Flags\n");
 
-        // Serialize each partition using a [csv::Writer]
-        let mut writer = csv::WriterBuilder::new()
-            .has_headers(false)
-            .from_writer(vec![]);
-
         for partition in &self.partitions {
+            // Any metadata is written as comments immediately above the partition
+            partition.write_annotations(&mut csv);
+
+            // Serialize the partition using a [csv::Writer], and append it to the text
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(vec![]);
             partition.write_csv(&mut writer)?;
-        }
 
-        // Append the serialized partitions to the header text, leaving us with our
-        // completed CSV text
-        csv.push_str(&String::from_utf8_lossy(&writer.into_inner().unwrap()));
+            csv.push_str(&String::from_utf8_lossy(&writer.into_inner().unwrap()));
+        }
 
         Ok(csv)
     }
 
     /// Validate a partition table
     pub fn validate(&self) -> Result<(), Error> {
-        use self::partition::{APP_PARTITION_ALIGNMENT, DATA_PARTITION_ALIGNMENT};
-
-        const MAX_APP_PART_SIZE: u32 = 0x100_0000; // 16MB
-        const OTADATA_SIZE: u32 = 0x2000; // 8kB
+        self.validate_with(&ValidationContext::default())
+    }
 
+    /// Validate a partition table using the provided [ValidationContext]
+    pub fn validate_with(&self, ctx: &ValidationContext) -> Result<(), Error> {
         // There must be at least one partition with type 'app'
         if self.find_by_type(Type::App).is_none() {
             return Err(Error::NoAppPartition);
@@ -293,41 +387,15 @@ impl PartitionTable {
         }
 
         for partition in &self.partitions {
-            // Partitions of type 'app' have to be placed at offsets aligned to 0x10000
-            // (64k)
-            if partition.ty() == Type::App && partition.offset().rem(APP_PARTITION_ALIGNMENT) != 0 {
-                return Err(Error::UnalignedPartition);
-            }
-
-            // Partitions of type 'data' have to be placed at offsets aligned to 0x1000 (4k)
-            if partition.ty() == Type::Data && partition.offset().rem(DATA_PARTITION_ALIGNMENT) != 0
-            {
-                return Err(Error::UnalignedPartition);
-            }
-
-            // App partitions cannot exceed 16MB; see:
-            // https://github.com/espressif/esp-idf/blob/c212305/components/bootloader_support/src/esp_image_format.c#L158-L161
-            if partition.ty() == Type::App && partition.size() > MAX_APP_PART_SIZE {
-                return Err(Error::PartitionTooLarge(partition.name()));
-            }
-
-            if partition.ty() == Type::Data
-                && partition.subtype() == SubType::Data(DataType::Ota)
-                && partition.size() != OTADATA_SIZE
-            {
-                return Err(Error::InvalidOtadataPartitionSize);
-            }
+            Self::validate_partition(partition, ctx)?;
         }
 
-        for partition_a in &self.partitions {
-            for partition_b in &self.partitions {
-                // Do not compare partitions with themselves :)
-                if partition_a == partition_b {
-                    continue;
-                }
-
-                // Partitions cannot have conflicting names
-                if partition_a.name() == partition_b.name() {
+        for (i, partition_a) in self.partitions.iter().enumerate() {
+            // Do not compare partitions with themselves :)
+            for partition_b in self.partitions.iter().skip(i + 1) {
+                // Partitions cannot have conflicting names; compare the labels as they are
+                // written to flash, as this is what ESP-IDF will see
+                if partition_a.label() == partition_b.label() {
                     return Err(Error::DuplicatePartitions(partition_a.name()));
                 }
 
@@ -341,6 +409,108 @@ impl PartitionTable {
             }
         }
 
+        // Partitions must be large enough (and not too large) for their contents
+        validation::size::check(self, ctx)?;
+
+        if let Some(scheme) = ctx.secure_boot() {
+            validation::secure_boot::check(self, ctx, scheme)?;
+        }
+
+        // Finally, run any user-defined rules
+        validation::rule::check(self, ctx)?;
+
+        Ok(())
+    }
+
+    /// Validate the properties of a single partition which do not depend on
+    /// the rest of the partition table
+    pub(crate) fn validate_partition(
+        partition: &Partition,
+        ctx: &ValidationContext,
+    ) -> Result<(), Error> {
+        use self::partition::{APP_PARTITION_ALIGNMENT, DATA_PARTITION_ALIGNMENT, OTADATA_SIZE};
+
+        // Partition names must fit within the NUL-terminated label field
+        validation::check_name(&partition.name(), ctx)?;
+
+        // Partitions must fit within the addressable flash
+        validation::check_bounds(partition, ctx)?;
+
+        // Partitions must only use features supported by the targeted ESP-IDF version
+        validation::version::check(partition, ctx)?;
+
+        // Partitions of type 'app' have to be placed at offsets aligned to 0x10000
+        // (64k)
+        if partition.ty() == Type::App && partition.offset().rem(APP_PARTITION_ALIGNMENT) != 0 {
+            return Err(Error::UnalignedPartition);
+        }
+
+        // Partitions of type 'data' have to be placed at offsets aligned to 0x1000 (4k)
+        if partition.ty() == Type::Data && partition.offset().rem(DATA_PARTITION_ALIGNMENT) != 0 {
+            return Err(Error::UnalignedPartition);
+        }
+
+        if partition.ty() == Type::Data
+            && partition.subtype() == SubType::Data(DataType::Ota)
+            && partition.size() != OTADATA_SIZE
+        {
+            return Err(Error::InvalidOtadataPartitionSize);
+        }
+
+        Ok(())
+    }
+
+    /// Check the partition table for likely mistakes which do not make it
+    /// invalid
+    ///
+    /// This is intended to be used in addition to [PartitionTable::validate];
+    /// lints are advisory, and it is up to the caller how to report them.
+    pub fn lint(&self, ctx: &ValidationContext) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        validation::size::lint(self, ctx, &mut lints);
+        normalize::lint(self, &mut lints);
+        validation::rule::lint(self, ctx, &mut lints);
+
+        lints
+    }
+}
+
+/// Partition tables are formatted as aligned CSV, in the column layout of the
+/// tables which ship with ESP-IDF, with sizes in human-readable form
+impl core::fmt::Display for PartitionTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        const HEADER: [&str; 6] = ["# Name", "Type", "SubType", "Offset", "Size", "Flags"];
+
+        let rows = self
+            .partitions
+            .iter()
+            .map(|p| p.columns())
+            .collect::<Vec<_>>();
+
+        // Each column is wide enough for its widest value, followed by a comma
+        let mut widths = HEADER.map(str::len);
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.len());
+            }
+        }
+
+        writeln!(f, "# ESP-IDF Partition Table")?;
+
+        let header = HEADER.map(String::from);
+        for row in std::iter::once(&header).chain(&rows) {
+            let mut line = String::new();
+            for (i, (column, width)) in row.iter().zip(widths).enumerate() {
+                if i + 1 < row.len() {
+                    line.push_str(&format!("{:<1$} ", format!("{column},"), width + 1));
+                } else {
+                    line.push_str(column);
+                }
+            }
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
         Ok(())
     }
 }
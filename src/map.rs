@@ -0,0 +1,207 @@
+use crate::{
+    FlashSize,
+    Partition,
+    PartitionTable,
+    Region,
+    ValidationContext,
+    partition::format_size,
+};
+
+// The default width of the bars, in characters
+const DEFAULT_WIDTH: usize = 64;
+
+/// A region of flash, as shown by a [FlashMap]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Segment<'a> {
+    /// A region reserved for the bootloader or partition table
+    Reserved(&'static str, Region),
+    /// A partition
+    Partition(&'a Partition),
+    /// A region not allocated to any partition
+    Free(Region),
+}
+
+impl Segment<'_> {
+    pub(crate) fn offset(&self) -> u32 {
+        match self {
+            Segment::Reserved(_, region) | Segment::Free(region) => region.offset(),
+            Segment::Partition(partition) => partition.offset(),
+        }
+    }
+
+    pub(crate) fn end(&self) -> u64 {
+        match self {
+            Segment::Reserved(_, region) | Segment::Free(region) => region.end(),
+            Segment::Partition(partition) => partition.end(),
+        }
+    }
+
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Segment::Reserved(name, _) => format!("<{name}>"),
+            Segment::Partition(partition) => partition.name(),
+            Segment::Free(_) => "<free>".into(),
+        }
+    }
+}
+
+/// The size of flash to scale a map of the partition table to, along with the
+/// regions of flash in order of their offsets
+///
+/// If the [ValidationContext] does not specify a flash size, the smallest
+/// which fits the partition table is assumed.
+pub(crate) fn segments<'a>(
+    table: &'a PartitionTable,
+    ctx: &ValidationContext,
+) -> (Option<FlashSize>, Vec<Segment<'a>>) {
+    let reserved = ctx.reserved_regions();
+
+    let flash_size = ctx.flash_size().or_else(|| {
+        let end = reserved
+            .iter()
+            .map(|(_, region)| region.end())
+            .chain(table.partitions().iter().map(|p| p.end()))
+            .max()
+            .unwrap_or_default();
+
+        FlashSize::fitting(end)
+    });
+
+    let ctx = match flash_size {
+        Some(size) => ctx.clone().with_flash_size(size),
+        None => ctx.clone(),
+    };
+
+    let mut segments = reserved
+        .into_iter()
+        .map(|(name, region)| Segment::Reserved(name, region))
+        .chain(table.partitions().iter().map(Segment::Partition))
+        .chain(table.gaps_with(&ctx).map(Segment::Free))
+        .collect::<Vec<_>>();
+    segments.sort_by_key(|segment| segment.offset());
+
+    (flash_size, segments)
+}
+
+/// An ASCII bar chart of a partition table's layout in flash
+///
+/// Each partition, each gap between partitions and each of the regions
+/// reserved for the bootloader and the partition table is drawn on its own
+/// line, as a bar whose position and length are scaled to the flash size.
+/// Partitions are drawn with `#`, reserved regions with `=` and free space
+/// with `.`:
+///
+/// ```text
+/// Flash map (2MB)
+/// 0x000000  <bootloader>        32K  |=                               |
+/// 0x008000  <partition table>    4K  |=                               |
+/// 0x009000  nvs                 24K  |#                               |
+/// 0x00f000  phy_init             4K  |#                               |
+/// 0x010000  factory              1M  | ################               |
+/// 0x110000  <free>             960K  |                 ...............|
+/// ```
+///
+/// Constructed by [PartitionTable::flash_map].
+#[derive(Debug, Clone)]
+pub struct FlashMap<'a> {
+    flash_size: Option<FlashSize>,
+    segments: Vec<Segment<'a>>,
+    width: usize,
+}
+
+impl FlashMap<'_> {
+    /// Set the width of the bars, in characters
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    // Return the range of columns covered by the bar of a segment
+    fn columns(&self, segment: &Segment, scale: u64) -> (usize, usize) {
+        let width = self.width as u64;
+
+        let start = (u64::from(segment.offset()) * width / scale).min(width - 1);
+        let end = (segment.end() * width)
+            .div_ceil(scale)
+            .clamp(start + 1, width);
+
+        (start as usize, end as usize)
+    }
+}
+
+impl core::fmt::Display for FlashMap<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let scale = self
+            .flash_size
+            .map_or(1 << 32, |size| u64::from(size.bytes()));
+
+        match self.flash_size {
+            Some(size) => writeln!(f, "Flash map ({size})")?,
+            None => writeln!(f, "Flash map (4GB address space)")?,
+        }
+
+        let digits = format!("{:x}", scale - 1).len();
+        let labels = self.segments.iter().map(Segment::label).collect::<Vec<_>>();
+        let sizes = self
+            .segments
+            .iter()
+            .map(|s| format_size(s.end() - u64::from(s.offset())))
+            .collect::<Vec<_>>();
+
+        let label_width = labels.iter().map(String::len).max().unwrap_or_default();
+        let size_width = sizes.iter().map(String::len).max().unwrap_or_default();
+
+        for ((segment, label), size) in self.segments.iter().zip(&labels).zip(&sizes) {
+            let fill = match segment {
+                Segment::Reserved(..) => '=',
+                Segment::Partition(..) => '#',
+                Segment::Free(..) => '.',
+            };
+
+            let (start, end) = self.columns(segment, scale);
+            let bar = (0..self.width)
+                .map(|column| {
+                    if (start..end).contains(&column) {
+                        fill
+                    } else {
+                        ' '
+                    }
+                })
+                .collect::<String>();
+
+            writeln!(
+                f,
+                "{:#0digits$x}  {label:<label_width$}  {size:>size_width$}  |{bar}|",
+                segment.offset(),
+                digits = digits + 2,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartitionTable {
+    /// Draw an ASCII bar chart of the partition table's layout in flash
+    ///
+    /// See [PartitionTable::flash_map_with] for more information.
+    pub fn flash_map(&self) -> FlashMap<'_> {
+        self.flash_map_with(&ValidationContext::default())
+    }
+
+    /// Draw an ASCII bar chart of the partition table's layout in flash
+    ///
+    /// The map is scaled to the flash size specified by the
+    /// [ValidationContext], or else to the smallest flash size which fits the
+    /// partition table. The reserved regions are determined by the partition
+    /// table offset.
+    pub fn flash_map_with(&self, ctx: &ValidationContext) -> FlashMap<'_> {
+        let (flash_size, segments) = segments(self, ctx);
+
+        FlashMap {
+            flash_size,
+            segments,
+            width: DEFAULT_WIDTH,
+        }
+    }
+}
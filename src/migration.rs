@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{DataType, Error, Partition, PartitionTable, Query};
+
+/// What happens to the contents of a partition when the partition table is
+/// changed
+///
+/// Variants are ordered by severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStatus {
+    /// The partition keeps its offset, type and subtype, and does not shrink,
+    /// so its contents are unaffected
+    Preserved,
+    /// The partition moves, so its contents must be copied to the new offset
+    Relocated,
+    /// The partition shrinks, so the contents beyond its new end are lost
+    Truncated,
+    /// The partition both moves and shrinks, so its contents must be copied
+    /// to the new offset and those beyond its new end are lost
+    RelocatedAndTruncated,
+    /// The partition is removed, or its type or subtype changes, so its
+    /// contents are lost
+    Destroyed,
+}
+
+impl MigrationStatus {
+    /// Does the partition move?
+    pub fn is_relocated(&self) -> bool {
+        matches!(
+            self,
+            MigrationStatus::Relocated | MigrationStatus::RelocatedAndTruncated
+        )
+    }
+
+    /// Does the partition shrink?
+    pub fn is_truncated(&self) -> bool {
+        matches!(
+            self,
+            MigrationStatus::Truncated | MigrationStatus::RelocatedAndTruncated
+        )
+    }
+}
+
+impl core::fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", serde_plain::to_string(self).unwrap())
+    }
+}
+
+/// The effect of changing the partition table on a single partition
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct PartitionMigration {
+    old: Partition,
+    new: Option<Partition>,
+    status: MigrationStatus,
+}
+
+impl PartitionMigration {
+    /// Return the partition in the old partition table
+    pub fn before(&self) -> &Partition {
+        &self.old
+    }
+
+    /// Return the matching partition in the new partition table, if any
+    ///
+    /// See [PartitionTable::analyze_migration] for how partitions are matched.
+    pub fn after(&self) -> Option<&Partition> {
+        self.new.as_ref()
+    }
+
+    /// Return what happens to the contents of the partition
+    pub fn status(&self) -> MigrationStatus {
+        self.status
+    }
+}
+
+/// An analysis of the effect of changing the partition table on a device
+/// which is already in the field
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct MigrationReport {
+    partitions: Vec<PartitionMigration>,
+    running_slot: Option<String>,
+    running_slot_clobbered: bool,
+    otadata_clobbered: bool,
+}
+
+impl MigrationReport {
+    /// Return the effect on each partition of the old partition table, in
+    /// table order
+    pub fn partitions(&self) -> &[PartitionMigration] {
+        &self.partitions
+    }
+
+    /// Return the effect on the partition with the given name, if any
+    pub fn partition(&self, name: &str) -> Option<&PartitionMigration> {
+        self.partitions.iter().find(|p| p.old.name() == name)
+    }
+
+    /// Would the app partition the device is currently running from be
+    /// overwritten or moved?
+    ///
+    /// The running slot is clobbered unless a partition of the new table with
+    /// the same type and subtype starts at the same offset and is at least as
+    /// large, and no other partition overlaps it; its name may change.
+    ///
+    /// This is always `false` if no running slot was given.
+    pub fn running_slot_clobbered(&self) -> bool {
+        self.running_slot_clobbered
+    }
+
+    /// Would the OTA data partition be overwritten or moved, losing the
+    /// selection of the app to boot?
+    ///
+    /// See [MigrationReport::running_slot_clobbered] for how this is
+    /// determined.
+    pub fn otadata_clobbered(&self) -> bool {
+        self.otadata_clobbered
+    }
+
+    /// Can the new partition table be written without any further migration?
+    ///
+    /// This is the case only if every partition is preserved; relocated
+    /// partitions must have their contents copied first.
+    pub fn is_safe(&self) -> bool {
+        self.partitions
+            .iter()
+            .all(|p| p.status == MigrationStatus::Preserved)
+            && !self.running_slot_clobbered
+            && !self.otadata_clobbered
+    }
+}
+
+/// The report is rendered as text, one partition per line
+impl core::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for partition in &self.partitions {
+            write!(f, "{}: {}", partition.old.name(), partition.status)?;
+
+            if let Some(new) = partition
+                .new
+                .as_ref()
+                .filter(|_| partition.status != MigrationStatus::Destroyed)
+            {
+                let mut changes = Vec::new();
+                if new.name() != partition.old.name() {
+                    changes.push(format!("'{}' -> '{}'", partition.old.name(), new.name()));
+                }
+                if new.offset() != partition.old.offset() {
+                    changes.push(format!(
+                        "{:#x} -> {:#x}",
+                        partition.old.offset(),
+                        new.offset()
+                    ));
+                }
+                if new.size() != partition.old.size() {
+                    changes.push(format!(
+                        "{:#x} -> {:#x} bytes",
+                        partition.old.size(),
+                        new.size()
+                    ));
+                }
+
+                if !changes.is_empty() {
+                    write!(f, " ({})", changes.join(", "))?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        if let Some(slot) = self
+            .running_slot
+            .as_deref()
+            .filter(|_| self.running_slot_clobbered)
+        {
+            writeln!(f, "The running app partition '{slot}' would be clobbered")?;
+        }
+        if self.otadata_clobbered {
+            writeln!(f, "The OTA data partition would be clobbered")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartitionTable {
+    /// Analyze the effect of replacing the partition table with `new` on a
+    /// device in the field
+    ///
+    /// Partitions are matched by name. A partition whose name is absent from
+    /// `new` is instead matched with a partition at the same offset with the
+    /// same type and subtype, provided its name is not in this partition table,
+    /// so that a partition renamed in place keeps its contents. If the device
+    /// is currently running from an app partition, its name should be given
+    /// as `running_slot`; it must be present in this partition table.
+    pub fn analyze_migration(
+        &self,
+        new: &PartitionTable,
+        running_slot: Option<&str>,
+    ) -> Result<MigrationReport, Error> {
+        if let Some(slot) = running_slot.filter(|slot| self.find(slot).is_none()) {
+            return Err(Error::PartitionNotFound(slot.into()));
+        }
+
+        let partitions = self
+            .partitions
+            .iter()
+            .map(|old| {
+                let new = new
+                    .find(&old.name())
+                    .or_else(|| {
+                        new.partitions.iter().find(|p| {
+                            p.offset() == old.offset()
+                                && same_type(old, p)
+                                && self.find(&p.name()).is_none()
+                        })
+                    })
+                    .cloned();
+                let status = match &new {
+                    Some(new) if same_type(old, new) => {
+                        match (new.offset() != old.offset(), new.size() < old.size()) {
+                            (false, false) => MigrationStatus::Preserved,
+                            (true, false) => MigrationStatus::Relocated,
+                            (false, true) => MigrationStatus::Truncated,
+                            (true, true) => MigrationStatus::RelocatedAndTruncated,
+                        }
+                    }
+                    _ => MigrationStatus::Destroyed,
+                };
+
+                PartitionMigration {
+                    old: old.clone(),
+                    new,
+                    status,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // A partition is clobbered if its bytes are no longer covered by a partition
+        // with the same contents, or are overwritten by any other partition; this
+        // is independent of names, so renaming a partition in place is harmless
+        let clobbered = |old: &Partition| {
+            let keeps = |p: &Partition| {
+                p.offset() == old.offset() && p.size() >= old.size() && same_type(old, p)
+            };
+
+            !new.partitions.iter().any(keeps)
+                || new.partitions.iter().any(|p| !keeps(p) && p.overlaps(old))
+        };
+
+        let running_slot_clobbered = running_slot
+            .and_then(|slot| self.find(slot))
+            .is_some_and(clobbered);
+        let otadata_clobbered = self
+            .query(Query::new().with_subtype(DataType::Ota))
+            .next()
+            .is_some_and(clobbered);
+
+        Ok(MigrationReport {
+            partitions,
+            running_slot: running_slot.map(String::from),
+            running_slot_clobbered,
+            otadata_clobbered,
+        })
+    }
+}
+
+/// Do the two partitions have the same type and subtype, and so hold the same
+/// kind of contents?
+fn same_type(old: &Partition, new: &Partition) -> bool {
+    new.ty() == old.ty()
+        && new.subtype().canonicalize(new.ty()) == old.subtype().canonicalize(old.ty())
+}
@@ -0,0 +1,100 @@
+use crate::{Lint, PARTITION_TABLE_SIZE, PartitionTable, ValidationContext};
+
+impl PartitionTable {
+    /// Bring the partition table into its canonical form
+    ///
+    /// See [PartitionTable::normalize_with] for more information.
+    pub fn normalize(&mut self) {
+        self.normalize_with(&ValidationContext::default());
+    }
+
+    /// Bring the partition table into its canonical form, using the
+    /// partition table offset of the provided [ValidationContext]
+    ///
+    /// Two partition tables which describe the same layout compare (and hash)
+    /// equal once normalized. Normalization:
+    ///
+    /// - places any partition with an offset of `0`, which is never a valid
+    ///   partition offset, at the first offset following the preceding
+    ///   partition (or the partition table) which is aligned for its type;
+    ///   unlike CSV rows which omit the offset, `data` partitions are aligned
+    ///   to 4kB rather than 4 bytes, so that they are valid once placed
+    /// - sorts the partitions by offset
+    /// - uses the named [SubType] variant wherever one exists
+    /// - removes any flags which have no effect, see
+    ///   [Partition::effective_flags]
+    ///
+    /// [SubType]: crate::SubType
+    /// [Partition::effective_flags]: crate::Partition::effective_flags
+    pub fn normalize_with(&mut self, ctx: &ValidationContext) {
+        let mut next = u64::from(ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+
+        for partition in &mut self.partitions {
+            if partition.offset() == 0 {
+                // Should this overflow, the partition is left at the top of the address space
+                // where it will fail validation
+                let offset = next.next_multiple_of(u64::from(partition.ty().alignment()));
+                partition.set_offset(u32::try_from(offset).unwrap_or(u32::MAX));
+            }
+
+            next = partition.end();
+            partition.normalize();
+        }
+
+        self.partitions.sort_by_key(|p| p.offset());
+    }
+
+    /// Is the partition table already in its canonical form?
+    ///
+    /// See [PartitionTable::normalize] for more information.
+    pub fn is_normalized(&self) -> bool {
+        self.is_normalized_with(&ValidationContext::default())
+    }
+
+    /// Is the partition table already in its canonical form, using the
+    /// partition table offset of the provided [ValidationContext]?
+    ///
+    /// See [PartitionTable::normalize_with] for more information.
+    pub fn is_normalized_with(&self, ctx: &ValidationContext) -> bool {
+        let mut normalized = self.clone();
+        normalized.normalize_with(ctx);
+
+        *self == normalized
+    }
+}
+
+pub(crate) fn lint(table: &PartitionTable, lints: &mut Vec<Lint>) {
+    if !table.partitions().is_sorted_by_key(|p| p.offset()) {
+        lints.push(Lint::new(
+            None,
+            "partitions are not sorted by offset, which confuses tools such as `parttool.py`; \
+             see `PartitionTable::normalize`",
+        ));
+    }
+
+    for partition in table.partitions() {
+        if partition.subtype() != partition.subtype().canonicalize(partition.ty()) {
+            lints.push(Lint::new(
+                Some(partition),
+                format!(
+                    "subtype {} is given numerically, but has the name '{}'",
+                    partition.subtype(),
+                    partition.subtype().canonicalize(partition.ty())
+                ),
+            ));
+        }
+
+        let ignored = partition.flags() - partition.effective_flags();
+        if !ignored.is_empty() {
+            lints.push(Lint::new(
+                Some(partition),
+                format!(
+                    "flags '{}' have no effect for partitions of type '{}' and subtype '{}'",
+                    ignored,
+                    partition.ty(),
+                    partition.subtype()
+                ),
+            ));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{AppType, DataType, Error, Partition, PartitionTable, Query, SubType, Type};
+
+/// A view of the partitions involved in over-the-air (OTA) updates
+///
+/// For more information regarding OTA updates, please refer to the ESP-IDF
+/// documentation:
+/// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-reference/system/ota.html>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtaLayout<'a> {
+    slots: Vec<&'a Partition>,
+    otadata: &'a Partition,
+    factory: Option<&'a Partition>,
+    test: Option<&'a Partition>,
+}
+
+impl<'a> OtaLayout<'a> {
+    /// Determine the OTA layout of the given partition table
+    ///
+    /// The partition table must contain an `otadata` partition, and OTA app
+    /// partitions numbered consecutively from `ota_0`; the bootloader maps the
+    /// sequence number stored in `otadata` onto this numbering. Unless there is
+    /// a factory app to update from, there must be at least two OTA slots.
+    pub fn new(table: &'a PartitionTable) -> Result<Self, Error> {
+        let invalid = |reason: String| Err(Error::InvalidOtaLayout(reason));
+
+        let apps = table
+            .partitions()
+            .iter()
+            .filter(|p| p.ty() == Type::App)
+            .map(|p| (p, p.subtype().canonicalize(Type::App)))
+            .collect::<Vec<_>>();
+        let app = |ty: AppType| {
+            apps.iter()
+                .find(|(_, subtype)| *subtype == SubType::App(ty))
+                .map(|(p, _)| *p)
+        };
+
+        let mut slots = Vec::new();
+        for slot in 0..=(AppType::Ota_15 as usize - AppType::Ota_0 as usize) {
+            let ty = AppType::from_repr(AppType::Ota_0 as usize + slot).unwrap();
+            let mut partitions = apps
+                .iter()
+                .filter(|(_, subtype)| *subtype == SubType::App(ty));
+
+            match (partitions.next(), partitions.next()) {
+                (Some(_), Some((duplicate, _))) => {
+                    return invalid(format!(
+                        "partition '{}' is a second OTA app partition with subtype 'ota_{slot}'",
+                        duplicate.name()
+                    ));
+                }
+                (Some((partition, _)), None) if slots.len() == slot => slots.push(*partition),
+                (Some((partition, _)), None) => {
+                    return invalid(format!(
+                        "partition '{}' has subtype 'ota_{slot}', but there is no OTA app partition \
+                         with subtype 'ota_{}'",
+                        partition.name(),
+                        slots.len()
+                    ));
+                }
+                (None, _) => {}
+            }
+        }
+
+        let factory = app(AppType::Factory);
+        let test = app(AppType::Test);
+
+        if slots.is_empty() {
+            return invalid("there are no OTA app partitions".into());
+        }
+        if slots.len() == 1 && factory.is_none() {
+            return invalid(format!(
+                "there is only one OTA app partition ('{}') and no factory app, so the running \
+                 app cannot be updated",
+                slots[0].name()
+            ));
+        }
+
+        let Some(otadata) = table.query(Query::new().with_subtype(DataType::Ota)).next() else {
+            return invalid("there is no 'otadata' partition".into());
+        };
+
+        Ok(Self {
+            slots,
+            otadata,
+            factory,
+            test,
+        })
+    }
+
+    /// Return the OTA app partitions, ordered by their subtype
+    pub fn slots(&self) -> &[&'a Partition] {
+        &self.slots
+    }
+
+    /// Return the OTA data partition, which selects the app to boot
+    pub fn otadata(&self) -> &'a Partition {
+        self.otadata
+    }
+
+    /// Return the factory app partition, if any
+    pub fn factory(&self) -> Option<&'a Partition> {
+        self.factory
+    }
+
+    /// Return the test app partition, if any
+    pub fn test(&self) -> Option<&'a Partition> {
+        self.test
+    }
+
+    /// Return the OTA slot which an update should be written to, when running
+    /// from the given partition
+    ///
+    /// As with ESP-IDF's `esp_ota_get_next_update_partition`, this is the slot
+    /// following the current one, wrapping around to `ota_0` after the last
+    /// slot. When running from any partition which is not an OTA slot, such as
+    /// the factory or test app, this is `ota_0`.
+    pub fn next_slot(&self, current: &Partition) -> &'a Partition {
+        let next = self
+            .slots
+            .iter()
+            .position(|slot| *slot == current)
+            .map_or(0, |index| (index + 1) % self.slots.len());
+
+        self.slots[next]
+    }
+}
+
+impl PartitionTable {
+    /// Return the OTA layout of the partition table
+    ///
+    /// See [OtaLayout::new] for more information.
+    pub fn ota_layout(&self) -> Result<OtaLayout<'_>, Error> {
+        OtaLayout::new(self)
+    }
+}
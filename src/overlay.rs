@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    Error,
+    Flags,
+    PARTITION_TABLE_SIZE,
+    Partition,
+    PartitionTable,
+    SubType,
+    Type,
+    ValidationContext,
+    partition::{DeserializedCsvPatch, parse_annotations},
+};
+
+/// Changes to a single partition, where omitted fields are left unchanged
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Patch {
+    name: String,
+    ty: Option<Type>,
+    subtype: Option<SubType>,
+    offset: Option<u32>,
+    size: Option<u32>,
+    flags: Option<Flags>,
+    // Metadata entries to set, in addition to any the partition already has
+    metadata: BTreeMap<String, String>,
+}
+
+impl From<DeserializedCsvPatch> for Patch {
+    fn from(patch: DeserializedCsvPatch) -> Self {
+        Self {
+            name: patch.name,
+            ty: patch.ty,
+            subtype: patch.subtype,
+            offset: patch.offset,
+            size: patch.size,
+            flags: patch.flags,
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<Partition> for Patch {
+    fn from(partition: Partition) -> Self {
+        Self {
+            name: partition.name(),
+            ty: Some(partition.ty()),
+            subtype: Some(partition.subtype()),
+            offset: Some(partition.offset()).filter(|&offset| offset != 0),
+            size: Some(partition.size()),
+            flags: Some(partition.flags()),
+            metadata: partition.metadata().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Change {
+    Remove(String),
+    Patch(Patch),
+}
+
+/// A named set of changes to a base partition table, such as the tweaks
+/// required for a particular board
+///
+/// Overlays are usually written in the same CSV format as partition tables,
+/// where each row either adds a partition, or overrides the fields of the
+/// partition with the same name. Fields which are left empty keep their value
+/// from the base partition table, and a row consisting only of a name
+/// prefixed with `-` removes that partition:
+///
+/// ```text
+/// # Name,     Type, SubType, Offset, Size, Flags
+/// user_fs,    ,     ,        ,       8M,
+/// zb_storage, data, fat,     ,       16K,
+/// -phy_init
+/// ```
+///
+/// Metadata annotations (e.g. `# @desc ...`) above a row are added to the
+/// partition's metadata, which is otherwise kept when it is modified.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Overlay {
+    name: String,
+    changes: Vec<Change>,
+}
+
+impl Overlay {
+    /// Construct a new, empty overlay with the given name
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Attempt to parse an overlay with the given name from CSV
+    pub fn try_from_str<N, S>(name: N, string: S) -> Result<Self, Error>
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        let data = string.into();
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .flexible(true)
+            .has_headers(false)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+
+        // Metadata is given by structured comments preceding each row, as in partition
+        // tables
+        let mut annotations = parse_annotations(&data).into_iter();
+
+        let mut overlay = Self::new(name);
+        for record in reader.deserialize() {
+            let patch: DeserializedCsvPatch = record?;
+            let metadata = annotations.next().unwrap_or_default();
+
+            overlay.changes.push(match patch.name.strip_prefix('-') {
+                Some(name) => Change::Remove(name.into()),
+                None => Change::Patch(Patch {
+                    metadata,
+                    ..patch.into()
+                }),
+            });
+        }
+
+        Ok(overlay)
+    }
+
+    /// Return the name of the overlay
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Add a partition, replacing any existing partition with the same name
+    ///
+    /// A partition with an offset of `0` is placed automatically. The
+    /// partition's metadata is added to that of any existing partition.
+    pub fn insert(mut self, partition: Partition) -> Self {
+        self.changes.push(Change::Patch(partition.into()));
+        self
+    }
+
+    /// Remove the partition with the given name
+    pub fn remove<S>(mut self, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.changes.push(Change::Remove(name.into()));
+        self
+    }
+
+    /// Change the size of the partition with the given name
+    pub fn resize<S>(mut self, name: S, size: u32) -> Self
+    where
+        S: Into<String>,
+    {
+        self.changes.push(Change::Patch(Patch {
+            name: name.into(),
+            ty: None,
+            subtype: None,
+            offset: None,
+            size: Some(size),
+            flags: None,
+            metadata: BTreeMap::new(),
+        }));
+        self
+    }
+}
+
+/// A partition table resulting from applying overlays to a base partition
+/// table, recording which overlay each partition came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlaidPartitionTable {
+    table: PartitionTable,
+    origins: HashMap<String, String>,
+}
+
+impl OverlaidPartitionTable {
+    /// Return the resulting partition table
+    pub fn table(&self) -> &PartitionTable {
+        &self.table
+    }
+
+    /// Return the resulting partition table, discarding the origins of its
+    /// partitions
+    pub fn into_table(self) -> PartitionTable {
+        self.table
+    }
+
+    /// Return the name of the overlay which last added or modified the
+    /// partition with the given name, or `None` if it is unchanged from the
+    /// base partition table (or does not exist)
+    pub fn origin(&self, name: &str) -> Option<&str> {
+        self.origins.get(name).map(String::as_str)
+    }
+}
+
+/// A partition being merged, along with the offset it would prefer to keep
+struct Entry {
+    partition: Partition,
+    preferred: Option<u32>,
+    pinned: bool,
+}
+
+impl PartitionTable {
+    /// Apply an overlay to the partition table
+    ///
+    /// See [PartitionTable::apply_overlays_with] for more information.
+    pub fn apply_overlay(&self, overlay: &Overlay) -> Result<OverlaidPartitionTable, Error> {
+        self.apply_overlays_with([overlay], &ValidationContext::default())
+    }
+
+    /// Apply each of the overlays to the partition table in turn
+    ///
+    /// Once all overlays have been applied, partitions are placed again in
+    /// table order. Partitions given an explicit offset by an overlay are
+    /// placed there; all others keep their previous offset unless a preceding
+    /// partition has grown, in which case they are moved down to follow it.
+    /// New partitions without an offset are placed after the last partition.
+    /// The result is then validated using the given [ValidationContext].
+    pub fn apply_overlays_with<'a, I>(
+        &self,
+        overlays: I,
+        ctx: &ValidationContext,
+    ) -> Result<OverlaidPartitionTable, Error>
+    where
+        I: IntoIterator<Item = &'a Overlay>,
+    {
+        let mut entries = self
+            .partitions
+            .iter()
+            .map(|partition| Entry {
+                partition: partition.clone(),
+                preferred: Some(partition.offset()),
+                pinned: false,
+            })
+            .collect::<Vec<_>>();
+        let mut origins = HashMap::new();
+
+        for overlay in overlays {
+            for change in &overlay.changes {
+                let patch = match change {
+                    Change::Remove(name) => {
+                        let index = entries
+                            .iter()
+                            .position(|e| e.partition.name() == *name)
+                            .ok_or_else(|| Error::PartitionNotFound(name.clone()))?;
+
+                        entries.remove(index);
+                        origins.remove(name);
+                        continue;
+                    }
+                    Change::Patch(patch) => patch,
+                };
+
+                match entries
+                    .iter_mut()
+                    .find(|e| e.partition.name() == patch.name)
+                {
+                    Some(entry) => entry.patch(patch),
+                    None => entries.push(Entry::new(patch, overlay)?),
+                }
+
+                origins.insert(patch.name.clone(), overlay.name.clone());
+            }
+        }
+
+        let mut next = u64::from(ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+        for entry in &mut entries {
+            let partition = &mut entry.partition;
+
+            if !entry.pinned {
+                let offset = next
+                    .next_multiple_of(u64::from(partition.ty().alignment()))
+                    .max(entry.preferred.map_or(0, u64::from));
+                // Should this overflow, the partition is left at the top of the address space
+                // where it will fail validation
+                partition.set_offset(u32::try_from(offset).unwrap_or(u32::MAX));
+            }
+
+            next = partition.end();
+        }
+
+        let mut partitions = entries.into_iter().map(|e| e.partition).collect::<Vec<_>>();
+        partitions.sort_by_key(|p| p.offset());
+
+        let table = PartitionTable::new(partitions);
+        table.validate_with(ctx)?;
+
+        Ok(OverlaidPartitionTable { table, origins })
+    }
+}
+
+impl Entry {
+    fn new(patch: &Patch, overlay: &Overlay) -> Result<Self, Error> {
+        let missing = |field: &str| {
+            Error::InvalidOverlay(format!(
+                "partition '{}' added by overlay '{}' has no {field}",
+                patch.name, overlay.name
+            ))
+        };
+
+        let ty = patch.ty.ok_or_else(|| missing("type"))?;
+        let subtype = patch.subtype.ok_or_else(|| missing("subtype"))?;
+        let size = patch.size.ok_or_else(|| missing("size"))?;
+
+        let mut partition = Partition::new(
+            patch.name.clone(),
+            ty,
+            subtype.canonicalize(ty),
+            patch.offset.unwrap_or(0),
+            size,
+            patch.flags.unwrap_or(Flags::empty()),
+        );
+        partition.set_metadata(patch.metadata.clone());
+
+        Ok(Self {
+            partition,
+            preferred: None,
+            pinned: patch.offset.is_some(),
+        })
+    }
+
+    fn patch(&mut self, patch: &Patch) {
+        let partition = &mut self.partition;
+
+        if let Some(ty) = patch.ty {
+            partition.set_ty(ty);
+        }
+        let subtype = patch.subtype.unwrap_or(partition.subtype());
+        partition.set_subtype(subtype.canonicalize(partition.ty()));
+        if let Some(offset) = patch.offset {
+            partition.set_offset(offset);
+        }
+        if let Some(size) = patch.size {
+            partition.set_size(size);
+        }
+        if let Some(flags) = patch.flags {
+            partition.set_flags(flags);
+        }
+
+        let mut metadata = partition.metadata().clone();
+        metadata.extend(patch.metadata.clone());
+        partition.set_metadata(metadata);
+
+        if patch.offset.is_some() {
+            self.preferred = patch.offset;
+            self.pinned = true;
+        }
+    }
+}
@@ -1,8 +1,12 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
 use deku::DekuRead;
 use regex::Regex;
-use serde::{Deserialize, Deserializer, de::Error};
+use serde::{
+    Deserialize,
+    Deserializer,
+    de::{Error, IntoDeserializer, value::StrDeserializer},
+};
 
 use super::{
     APP_PARTITION_ALIGNMENT,
@@ -37,16 +41,21 @@ impl From<DeserializedBinPartition> for Partition {
             Type::Custom(..) => SubType::from(part.subtype),
         };
 
+        // The label is NUL-terminated, anything following the terminator is ignored
+        let len = part
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(MAX_NAME_LEN);
+
         Self {
-            name: String::from_utf8_lossy(&part.name)
-                .to_string()
-                .trim_matches(char::from(0))
-                .to_string(),
+            name: String::from_utf8_lossy(&part.name[..len]).to_string(),
             ty,
             subtype,
             offset: part.offset.unwrap(),
             size: part.size,
             flags: Flags::from_bits(part.flags).unwrap(),
+            metadata: BTreeMap::new(),
         }
     }
 }
@@ -79,19 +88,46 @@ impl From<DeserializedCsvPartition> for Partition {
         };
 
         Self {
-            name: part.name.trim_matches(char::from(0)).to_string(),
+            name: part.name,
             ty: part.ty,
             subtype,
             offset: part.offset.unwrap(),
             size: part.size,
             flags: Flags::from_bits(part.flags).unwrap(),
+            metadata: BTreeMap::new(),
         }
     }
 }
 
+/// A row of an overlay, in which any field other than the name may be omitted
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeserializedCsvPatch {
+    #[serde(deserialize_with = "deserialize_partition_name")]
+    pub(crate) name: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_optional_partition_type")]
+    pub(crate) ty: Option<Type>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_optional_partition_subtype")]
+    pub(crate) subtype: Option<SubType>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_partition_offset")]
+    pub(crate) offset: Option<u32>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_partition_offset_or_size")]
+    pub(crate) size: Option<u32>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_optional_partition_flags")]
+    pub(crate) flags: Option<Flags>,
+}
+
 impl DeserializedCsvPartition {
-    /// Ensure that the `offset` field is set (and is correctly aligned)
-    pub(crate) fn fix_offset(&mut self, offset: u32) -> u32 {
+    /// Ensure that the `offset` field is set (and is correctly aligned),
+    /// returning the end address of the partition
+    ///
+    /// Addresses are 64-bit so that a partition ending at the very top of the
+    /// 32-bit address space does not overflow.
+    pub(crate) fn fix_offset(&mut self, offset: u64) -> Result<u64, crate::Error> {
         if self.offset.is_none() {
             let alignment = if self.ty == Type::App {
                 APP_PARTITION_ALIGNMENT
@@ -99,16 +135,17 @@ impl DeserializedCsvPartition {
                 4 // 4 bytes, 32 bits
             };
 
-            let offset = if offset % alignment != 0 {
-                offset + alignment - (offset % alignment)
-            } else {
-                offset
-            };
+            let offset = offset.next_multiple_of(u64::from(alignment));
+            let offset = u32::try_from(offset).map_err(|_| crate::Error::PartitionOutOfBounds {
+                name: self.name.clone(),
+                end: offset + u64::from(self.size),
+                limit: 1 << 32,
+            })?;
 
             self.offset = Some(offset);
         }
 
-        self.offset.unwrap() + self.size
+        Ok(u64::from(self.offset.unwrap()) + u64::from(self.size))
     }
 }
 
@@ -116,20 +153,11 @@ fn deserialize_partition_name<'de, D>(deserializer: D) -> Result<String, D::Erro
 where
     D: Deserializer<'de>,
 {
-    let buf = String::deserialize(deserializer)?;
-
-    let bytes = if let Some((idx, _)) = buf.as_str().char_indices().nth(MAX_NAME_LEN) {
-        &buf.as_bytes()[..idx]
-    } else {
-        buf.as_bytes()
-    };
-
-    let mut name_bytes = [0u8; MAX_NAME_LEN + 1]; // Extra byte for the NULL terminator!
-    for (source, dest) in bytes.iter().zip(name_bytes.iter_mut()) {
-        *dest = *source;
-    }
-
-    Ok(String::from_utf8_lossy(&name_bytes).to_string())
+    // Names are deliberately not truncated here; whether or not a name fits within
+    // the label field is checked when validating the partition table, so that
+    // an overly long name results in an error rather than a silently renamed
+    // partition.
+    String::deserialize(deserializer)
 }
 
 fn deserialize_partition_type<'de, D>(deserializer: D) -> Result<Type, D::Error>
@@ -203,6 +231,124 @@ where
     Ok(flags.bits())
 }
 
+fn deserialize_optional_partition_type<'de, D>(deserializer: D) -> Result<Option<Type>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_optional(deserializer, |d| deserialize_partition_type(d))
+}
+
+fn deserialize_optional_partition_subtype<'de, D>(
+    deserializer: D,
+) -> Result<Option<SubType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_optional(deserializer, |d| deserialize_partition_subtype(d))
+}
+
+fn deserialize_optional_partition_flags<'de, D>(deserializer: D) -> Result<Option<Flags>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let flags = deserialize_optional(deserializer, |d| deserialize_partition_flags(d))?;
+    Ok(flags.map(Flags::from_bits_truncate))
+}
+
+/// Deserialize an optional field, which is omitted if empty
+fn deserialize_optional<'de, D, T, F>(deserializer: D, f: F) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    F: FnOnce(StrDeserializer<'_, D::Error>) -> Result<T, D::Error>,
+{
+    let buf = String::deserialize(deserializer)?;
+    if buf.is_empty() {
+        Ok(None)
+    } else {
+        f(buf.as_str().into_deserializer()).map(Some)
+    }
+}
+
+/// Parse a size or offset in any format accepted in CSV, e.g. `4096`, `0x1000`
+/// or `4K`
+pub(crate) fn parse_offset_or_size(buf: &str) -> Result<u32, String> {
+    let deserializer: StrDeserializer<serde::de::value::Error> = buf.into_deserializer();
+
+    match deserialize_partition_offset_or_size(deserializer) {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => Err("missing size/offset".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Is the line of CSV a record, rather than a blank line or a comment?
+pub(crate) fn is_record(line: &str) -> bool {
+    !line.trim().is_empty() && !line.starts_with('#')
+}
+
+/// Collect the metadata annotations preceding each record of the CSV
+///
+/// Annotations are comments of the form `# @key value`, and apply to the next
+/// record; other comments and blank lines in between are ignored. Consecutive
+/// annotations with the same key are joined by a space, so that long values
+/// may be wrapped over several lines.
+pub(crate) fn parse_annotations(csv: &str) -> Vec<BTreeMap<String, String>> {
+    let mut annotations = Vec::new();
+    let mut pending = BTreeMap::<String, String>::new();
+    let mut previous = None;
+
+    for line in csv.lines() {
+        if is_record(line) {
+            annotations.push(core::mem::take(&mut pending));
+            previous = None;
+            continue;
+        }
+
+        let Some((key, value)) = parse_annotation(line) else {
+            previous = None;
+            continue;
+        };
+
+        match pending.get_mut(key) {
+            Some(existing) if previous == Some(key) => {
+                if !value.is_empty() {
+                    existing.push(' ');
+                    existing.push_str(value);
+                }
+            }
+            _ => {
+                pending.insert(key.into(), value.into());
+            }
+        }
+
+        previous = Some(key);
+    }
+
+    annotations
+}
+
+/// Can the character be used in the key of a metadata annotation?
+pub(crate) fn is_metadata_key(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+fn parse_annotation(line: &str) -> Option<(&str, &str)> {
+    let annotation = line
+        .trim()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix('@')?;
+    let (key, value) = annotation
+        .split_once(char::is_whitespace)
+        .unwrap_or((annotation, ""));
+
+    if key.is_empty() || !key.chars().all(is_metadata_key) {
+        return None;
+    }
+
+    Some((key, value.trim()))
+}
+
 fn deserialize_partition_offset_or_size<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
 where
     D: Deserializer<'de>,
@@ -218,14 +364,22 @@ where
         Ok(Some(integer))
     } else if let Some(captures) = re.captures(&buf) {
         // Size multiplier format (1k, 2M, etc.)
-        let digits = captures.get(1).unwrap().as_str().parse::<u32>().unwrap();
+        let digits = captures
+            .get(1)
+            .unwrap()
+            .as_str()
+            .parse::<u32>()
+            .map_err(|_| Error::custom("partition size/offset exceeds 4GB"))?;
         let multiplier = match captures.get(2).unwrap().as_str() {
             "k" | "K" => 1024,
             "m" | "M" => 1024 * 1024,
             _ => unreachable!(),
         };
 
-        Ok(Some(digits * multiplier))
+        digits
+            .checked_mul(multiplier)
+            .map(Some)
+            .ok_or_else(|| Error::custom("partition size/offset exceeds 4GB"))
     } else {
         Err(Error::custom("invalid partition size/offset format"))
     }
@@ -233,10 +387,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use serde::de::{
-        IntoDeserializer,
-        value::{Error as ValueError, StrDeserializer},
-    };
+    use serde::de::value::Error as ValueError;
 
     use super::*;
 
@@ -245,15 +396,16 @@ mod tests {
         let deserializer: StrDeserializer<ValueError> = "factory".into_deserializer();
         assert_eq!(
             deserialize_partition_name(deserializer),
-            Ok(String::from("factory\0\0\0\0\0\0\0\0\0\0"))
+            Ok(String::from("factory"))
         );
 
-        // Make sure long names are truncated!
+        // Make sure long names are NOT truncated, they are rejected by validation
         let deserializer: StrDeserializer<ValueError> =
             "areallylongpartitionname".into_deserializer();
-        let result = deserialize_partition_name(deserializer);
-        assert_eq!(result, Ok(String::from("areallylongparti\0")));
-        assert_eq!(result.unwrap().len(), 17);
+        assert_eq!(
+            deserialize_partition_name(deserializer),
+            Ok(String::from("areallylongpartitionname"))
+        );
     }
 
     #[test]
@@ -355,5 +507,17 @@ mod tests {
         // Offsets can optionally be omitted in some cases
         let deserializer: StrDeserializer<ValueError> = "".into_deserializer();
         assert_eq!(deserialize_partition_offset_or_size(deserializer), Ok(None));
+
+        // Values which do not fit in 32 bits are rejected rather than overflowing
+        let deserializer: StrDeserializer<ValueError> = "4096M".into_deserializer();
+        assert_eq!(
+            deserialize_partition_offset_or_size(deserializer),
+            Err(Error::custom("partition size/offset exceeds 4GB"))
+        );
+        let deserializer: StrDeserializer<ValueError> = "99999999999k".into_deserializer();
+        assert_eq!(
+            deserialize_partition_offset_or_size(deserializer),
+            Err(Error::custom("partition size/offset exceeds 4GB"))
+        );
     }
 }
@@ -1,16 +1,30 @@
 use core::cmp::{max, min};
+use std::collections::BTreeMap;
 
 use deku::DekuRead;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, EnumString, FromRepr, IntoEnumIterator, VariantNames};
 
-pub(crate) use self::de::{DeserializedBinPartition, DeserializedCsvPartition};
+pub(crate) use self::de::{
+    DeserializedBinPartition,
+    DeserializedCsvPartition,
+    DeserializedCsvPatch,
+    is_metadata_key,
+    is_record,
+    parse_annotations,
+    parse_offset_or_size,
+};
+use crate::Error;
 
 mod de;
 
-pub(crate) const APP_PARTITION_ALIGNMENT: u32 = 0x10000;
-pub(crate) const DATA_PARTITION_ALIGNMENT: u32 = 0x1000;
+/// The required offset alignment of [`Type::App`] partitions
+pub const APP_PARTITION_ALIGNMENT: u32 = 0x10000;
+/// The required offset alignment of [`Type::Data`] and [`Type::Custom`]
+/// partitions
+pub const DATA_PARTITION_ALIGNMENT: u32 = 0x1000;
 pub(crate) const MAX_NAME_LEN: usize = 16;
+pub(crate) const OTADATA_SIZE: u32 = 0x2000; // 8kB
 
 /// Supported partition types
 ///
@@ -21,7 +35,7 @@ pub(crate) const MAX_NAME_LEN: usize = 16;
 /// For additional information regarding the supported partition types, please
 /// refer to the ESP-IDF documentation:
 /// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-guides/partition-tables.html#type-field>
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, DekuRead)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, DekuRead)]
 #[deku(endian = "little", id_type = "u8")]
 #[serde(rename_all = "lowercase")]
 pub enum Type {
@@ -67,6 +81,14 @@ impl From<Type> for u8 {
 }
 
 impl Type {
+    /// The offset alignment required for partitions of this type
+    pub fn alignment(&self) -> u32 {
+        match self {
+            Type::App => APP_PARTITION_ALIGNMENT,
+            Type::Data | Type::Custom(..) => DATA_PARTITION_ALIGNMENT,
+        }
+    }
+
     /// Return a `String` stating which subtypes are allowed for the given type.
     ///
     /// This is useful for error handling in dependent packages.
@@ -95,7 +117,7 @@ impl Type {
 /// For additional information regarding the supported partition subtypes,
 /// please refer to the ESP-IDF documentation:
 /// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-guides/partition-tables.html#subtype>
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum SubType {
     App(AppType),
@@ -148,13 +170,35 @@ impl From<SubType> for u8 {
 
 impl SubType {
     /// Create a [SubType::App] variant from an integer value
+    ///
+    /// Values which do not correspond to a known [AppType] result in a
+    /// [SubType::Custom] variant.
     pub fn app(value: u8) -> Self {
-        Self::App(AppType::from_repr(value as usize).unwrap())
+        AppType::from_repr(value as usize).map_or(Self::Custom(value), Self::App)
     }
 
     /// Create a [SubType::Data] variant from an integer value
+    ///
+    /// Values which do not correspond to a known [DataType] result in a
+    /// [SubType::Custom] variant.
     pub fn data(value: u8) -> Self {
-        Self::Data(DataType::from_repr(value as usize).unwrap())
+        DataType::from_repr(value as usize).map_or(Self::Custom(value), Self::Data)
+    }
+
+    /// Return the canonical representation of the subtype for the given
+    /// [Type]
+    ///
+    /// The same subtype may be represented either by name or numerically (eg.
+    /// `SubType::Data(DataType::Nvs)` and `SubType::Custom(0x02)`); the named
+    /// variant is preferred whenever one exists for the type.
+    pub fn canonicalize(self, ty: Type) -> Self {
+        let value = u8::from(self);
+
+        match ty {
+            Type::App => Self::app(value),
+            Type::Data => Self::data(value),
+            Type::Custom(..) => Self::Custom(value),
+        }
     }
 }
 
@@ -169,6 +213,7 @@ impl SubType {
     Copy,
     PartialEq,
     Eq,
+    Hash,
     Deserialize,
     EnumIter,
     EnumString,
@@ -211,6 +256,7 @@ pub enum AppType {
     Copy,
     PartialEq,
     Eq,
+    Hash,
     Deserialize,
     EnumIter,
     EnumString,
@@ -248,10 +294,10 @@ bitflags::bitflags! {
     ///     - Note: `app` type partitions will always be encrypted, regardless of
     ///       whether this flag is set or not.
     /// - If `readonly` flag is set, the partition will be read-only. This flag is
-    ///   only supported for `data` type partitions except `ota` and `coredump`
-    ///   subtypes. This flag can help to protect against accidental writes to a
-    ///   partition that contains critical device-specific configuration data, e.g.
-    ///   factory data partition.
+    ///   not supported for `app` type partitions, nor for `data` type partitions
+    ///   of the `ota` and `coredump` subtypes. This flag can help to protect
+    ///   against accidental writes to a partition that contains critical
+    ///   device-specific configuration data, e.g. factory data partition.
     ///
     /// You can specify multiple flags by separating them with a colon. For example,
     /// `encrypted:readonly`.
@@ -260,7 +306,7 @@ bitflags::bitflags! {
     /// <https://docs.espressif.com/projects/esp-idf/en/v5.3.1/esp32/api-guides/partition-tables.html#flags>
     ///
     /// [Flash Encryption]: https://docs.espressif.com/projects/esp-idf/en/v5.3.1/esp32/security/flash-encryption.html
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
     pub struct Flags: u32 {
         /// Encrypted partition
         const ENCRYPTED = 0b0001;
@@ -269,8 +315,34 @@ bitflags::bitflags! {
     }
 }
 
+/// Flags are formatted as they are written in CSV, e.g. `encrypted:readonly`
+impl core::fmt::Display for Flags {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let mut flags = Vec::<&str>::new();
+        if self.contains(Flags::ENCRYPTED) {
+            flags.push("encrypted");
+        }
+        if self.contains(Flags::READONLY) {
+            flags.push("readonly");
+        }
+
+        write!(f, "{}", flags.join(":"))
+    }
+}
+
 /// A single partition definition
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+///
+/// In addition to the fields stored in the partition table, a partition may
+/// carry a map of free-form metadata such as a description or an owner. There
+/// is no room for metadata in the binary format, so it is dropped by
+/// [PartitionTable::to_bin]; in CSV it is written as structured comments
+/// (e.g. `# @desc Main application`) immediately above the partition's row.
+///
+/// Metadata does not affect the partition's layout, and so is not considered
+/// when comparing or hashing partitions.
+///
+/// [PartitionTable::to_bin]: crate::PartitionTable::to_bin
+#[derive(Debug, Clone, Eq, Deserialize, Serialize)]
 pub struct Partition {
     name: String,
     ty: Type,
@@ -278,6 +350,12 @@ pub struct Partition {
     offset: u32,
     size: u32,
     flags: Flags,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_metadata"
+    )]
+    metadata: BTreeMap<String, String>,
 }
 
 impl Partition {
@@ -293,9 +371,29 @@ impl Partition {
             offset,
             size,
             flags,
+            metadata: BTreeMap::new(),
         }
     }
 
+    /// Set a metadata entry, replacing any existing value for the key
+    ///
+    /// Keys may contain only ASCII letters, digits, `_`, `-` and `.`, and
+    /// values may not contain control characters (including newlines) or
+    /// begin or end with whitespace, so that they can be written as CSV
+    /// comments and read back unchanged.
+    pub fn with_metadata<K, V>(mut self, key: K, value: V) -> Result<Self, Error>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        let value = value.into();
+        check_metadata(&key, &value)?;
+
+        self.metadata.insert(key, value);
+        Ok(self)
+    }
+
     /// Return the partition's name
     pub fn name(&self) -> String {
         self.name.clone()
@@ -326,9 +424,110 @@ impl Partition {
         self.flags
     }
 
+    /// Return the partition's metadata
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Set the partition's name
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Set the partition's [Type]
+    pub(crate) fn set_ty(&mut self, ty: Type) {
+        self.ty = ty;
+    }
+
+    /// Set the partition's [SubType]
+    pub(crate) fn set_subtype(&mut self, subtype: SubType) {
+        self.subtype = subtype;
+    }
+
+    /// Set the partition's offset
+    pub(crate) fn set_offset(&mut self, offset: u32) {
+        self.offset = offset;
+    }
+
+    /// Set the partition's size
+    pub(crate) fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    /// Set the partition's flags
+    pub(crate) fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    /// Set the partition's metadata
+    pub(crate) fn set_metadata(&mut self, metadata: BTreeMap<String, String>) {
+        self.metadata = metadata;
+    }
+
+    /// Return a copy of the partition's flags with any flags which have no
+    /// effect for its type and subtype removed
+    ///
+    /// `app` partitions are always encrypted when Flash Encryption is enabled,
+    /// and, as in `gen_esp32part.py`, the `readonly` flag is supported for any
+    /// partition other than `app` partitions and `data` partitions of subtype
+    /// `ota` or `coredump`.
+    pub fn effective_flags(&self) -> Flags {
+        let mut flags = self.flags;
+
+        if self.ty == Type::App {
+            flags.remove(Flags::ENCRYPTED);
+        }
+
+        let readonly_unsupported = match self.ty {
+            Type::App => true,
+            Type::Data => matches!(
+                self.subtype.canonicalize(self.ty),
+                SubType::Data(DataType::Ota) | SubType::Data(DataType::Coredump)
+            ),
+            Type::Custom(..) => false,
+        };
+        if readonly_unsupported {
+            flags.remove(Flags::READONLY);
+        }
+
+        flags
+    }
+
+    /// Canonicalize the partition's subtype and remove any meaningless flags
+    pub(crate) fn normalize(&mut self) {
+        self.subtype = self.subtype.canonicalize(self.ty);
+        self.flags = self.effective_flags();
+    }
+
+    /// Return the address immediately following the end of the partition
+    ///
+    /// This is computed using 64-bit arithmetic, as a partition may end at the
+    /// very top of the 32-bit address space.
+    pub fn end(&self) -> u64 {
+        u64::from(self.offset) + u64::from(self.size)
+    }
+
     /// Does this partition overlap with another?
     pub fn overlaps(&self, other: &Partition) -> bool {
-        max(self.offset, other.offset) < min(self.offset + self.size, other.offset + other.size)
+        u64::from(max(self.offset, other.offset)) < min(self.end(), other.end())
+    }
+
+    /// Return the partition's name as it is stored in the binary label field
+    ///
+    /// Names which do not fit are truncated on a character boundary, so that
+    /// a multi-byte UTF-8 character is never split. Validation rejects such
+    /// names, however this ensures an unvalidated table is still written
+    /// sensibly.
+    pub(crate) fn label(&self) -> [u8; MAX_NAME_LEN] {
+        let mut len = self.name.len().min(MAX_NAME_LEN);
+        while !self.name.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        let mut label = [0u8; MAX_NAME_LEN];
+        label[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+
+        label
     }
 
     /// Write a record to the provided binary writer
@@ -343,41 +542,131 @@ impl Partition {
         writer.write_all(&self.offset.to_le_bytes())?;
         writer.write_all(&self.size.to_le_bytes())?;
 
-        let mut name_bytes = [0u8; 16];
-        for (source, dest) in self.name.bytes().zip(name_bytes.iter_mut()) {
-            *dest = source;
-        }
-        writer.write_all(&name_bytes)?;
+        writer.write_all(&self.label())?;
 
         writer.write_all(&self.flags.bits().to_le_bytes())?;
 
         Ok(())
     }
 
+    /// Write the partition's metadata as CSV comments, one line per entry
+    pub(crate) fn write_annotations(&self, csv: &mut String) {
+        for (key, value) in &self.metadata {
+            csv.push_str(format!("# @{key} {value}").trim_end());
+            csv.push('\n');
+        }
+    }
+
+    /// Return the partition's fields as they are displayed, with sizes in
+    /// human-readable form
+    pub(crate) fn columns(&self) -> [String; 6] {
+        [
+            self.name(),
+            self.ty.to_string(),
+            self.subtype.to_string(),
+            format!("{:#x}", self.offset),
+            format_size(u64::from(self.size)),
+            self.flags.to_string(),
+        ]
+    }
+
     /// Write a record to the provided [`csv::Writer`]
     pub fn write_csv<W>(&self, csv: &mut csv::Writer<W>) -> std::io::Result<()>
     where
         W: std::io::Write,
     {
-        let mut flags = Vec::<&str>::new();
-        if self.flags.contains(Flags::ENCRYPTED) {
-            flags.push("encrypted");
-        }
-        if self.flags.contains(Flags::READONLY) {
-            flags.push("readonly");
-        }
-
-        let flags = flags.join(":");
-
         csv.write_record(&[
             self.name(),
             self.ty.to_string(),
             self.subtype.to_string(),
             format!("{:#x}", self.offset),
             format!("{:#x}", self.size),
-            flags,
+            self.flags.to_string(),
         ])?;
 
         Ok(())
     }
 }
+
+impl PartialEq for Partition {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.ty == other.ty
+            && self.subtype == other.subtype
+            && self.offset == other.offset
+            && self.size == other.size
+            && self.flags == other.flags
+    }
+}
+
+impl core::hash::Hash for Partition {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.ty.hash(state);
+        self.subtype.hash(state);
+        self.offset.hash(state);
+        self.size.hash(state);
+        self.flags.hash(state);
+    }
+}
+
+/// Partitions are formatted as a row of CSV, in the style of ESP-IDF's
+/// `gen_esp32part.py`, e.g. `factory, app, factory, 0x10000, 1M,`
+impl core::fmt::Display for Partition {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.columns().join(", ").trim_end())
+    }
+}
+
+/// Check that a metadata entry can be written as a CSV annotation and read
+/// back unchanged
+fn check_metadata(key: &str, value: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| Error::InvalidMetadata {
+        key: key.into(),
+        reason: reason.into(),
+    };
+
+    if key.is_empty() {
+        return Err(invalid("key must not be empty"));
+    }
+    if !key.chars().all(is_metadata_key) {
+        return Err(invalid(
+            "key may contain only ASCII letters, digits, '_', '-' and '.'",
+        ));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(invalid("value must not contain control characters"));
+    }
+    if value.trim() != value {
+        return Err(invalid("value must not begin or end with whitespace"));
+    }
+
+    Ok(())
+}
+
+fn deserialize_metadata<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let metadata = BTreeMap::<String, String>::deserialize(deserializer)?;
+    for (key, value) in &metadata {
+        check_metadata(key, value).map_err(serde::de::Error::custom)?;
+    }
+
+    Ok(metadata)
+}
+
+/// Format a size as `gen_esp32part.py` does, using `M` or `K` suffixes where
+/// the size is a whole number of mebibytes or kibibytes
+pub(crate) fn format_size(size: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+
+    if size != 0 && size % MIB == 0 {
+        format!("{}M", size / MIB)
+    } else if size != 0 && size % KIB == 0 {
+        format!("{}K", size / KIB)
+    } else {
+        format!("{size:#x}")
+    }
+}
@@ -0,0 +1,275 @@
+//! The partition tables which ship with ESP-IDF
+//!
+//! ESP-IDF provides a number of preset partition tables, one of which is
+//! selected by the `CONFIG_PARTITION_TABLE_*` choice in `sdkconfig` unless a
+//! custom table is used. None of the presets specify partition offsets;
+//! instead, partitions are placed following the partition table, whose offset
+//! is set by `CONFIG_PARTITION_TABLE_OFFSET` (`0x8000` by default).
+//!
+//! For more information, see the ESP-IDF documentation:
+//! <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-guides/partition-tables.html#built-in-partition-tables>
+//!
+//! ```rust
+//! use esp_idf_part::presets::{self, Preset};
+//!
+//! let table = presets::two_ota(0x8000).unwrap();
+//! assert_eq!(table.find("ota_0").unwrap().offset(), 0x110000);
+//!
+//! let preset = Preset::from_kconfig("CONFIG_PARTITION_TABLE_SINGLE_APP").unwrap();
+//! assert_eq!(preset.coredump(), Some(Preset::SingleAppCoredump));
+//! ```
+
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{Error, PartitionTable, ValidationContext};
+
+/// A partition table which ships with ESP-IDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Preset {
+    /// `partitions_singleapp.csv`
+    SingleApp,
+    /// `partitions_singleapp_coredump.csv`
+    SingleAppCoredump,
+    /// `partitions_singleapp_encr_nvs.csv`
+    SingleAppEncryptedNvs,
+    /// `partitions_singleapp_large.csv`
+    SingleAppLarge,
+    /// `partitions_singleapp_large_coredump.csv`
+    SingleAppLargeCoredump,
+    /// `partitions_singleapp_large_encr_nvs.csv`
+    SingleAppLargeEncryptedNvs,
+    /// `partitions_two_ota.csv`
+    TwoOta,
+    /// `partitions_two_ota_coredump.csv`
+    TwoOtaCoredump,
+    /// `partitions_two_ota_encr_nvs.csv`
+    TwoOtaEncryptedNvs,
+    /// `partitions_two_ota_large.csv`
+    TwoOtaLarge,
+}
+
+impl Preset {
+    /// Return the preset selected by a `CONFIG_PARTITION_TABLE_*` choice, if
+    /// any
+    ///
+    /// The `CONFIG_` prefix is optional. `PARTITION_TABLE_CUSTOM` does not
+    /// select a preset, and so returns `None`.
+    pub fn from_kconfig(name: &str) -> Option<Self> {
+        let name = name.trim();
+        let name = name.strip_prefix("CONFIG_").unwrap_or(name);
+
+        Self::iter().find(|preset| preset.kconfig() == Some(name))
+    }
+
+    /// The name of the `CONFIG_PARTITION_TABLE_*` choice which selects this
+    /// preset, without the `CONFIG_` prefix
+    ///
+    /// The coredump variants do not have a choice of their own, see
+    /// [Preset::coredump].
+    pub fn kconfig(&self) -> Option<&'static str> {
+        match self {
+            Preset::SingleApp => Some("PARTITION_TABLE_SINGLE_APP"),
+            Preset::SingleAppEncryptedNvs => Some("PARTITION_TABLE_SINGLE_APP_ENCRYPTED_NVS"),
+            Preset::SingleAppLarge => Some("PARTITION_TABLE_SINGLE_APP_LARGE"),
+            Preset::SingleAppLargeEncryptedNvs => Some("PARTITION_TABLE_SINGLE_APP_LARGE_ENC_NVS"),
+            Preset::TwoOta => Some("PARTITION_TABLE_TWO_OTA"),
+            Preset::TwoOtaEncryptedNvs => Some("PARTITION_TABLE_TWO_OTA_ENCRYPTED_NVS"),
+            Preset::TwoOtaLarge => Some("PARTITION_TABLE_TWO_OTA_LARGE"),
+            Preset::SingleAppCoredump | Preset::SingleAppLargeCoredump | Preset::TwoOtaCoredump => {
+                None
+            }
+        }
+    }
+
+    /// Return the variant of this preset with a coredump partition, if any
+    ///
+    /// ESP-IDF uses this variant in place of the selected preset when
+    /// `CONFIG_ESP_COREDUMP_ENABLE_TO_FLASH` is set.
+    pub fn coredump(&self) -> Option<Self> {
+        match self {
+            Preset::SingleApp | Preset::SingleAppCoredump => Some(Preset::SingleAppCoredump),
+            Preset::SingleAppLarge | Preset::SingleAppLargeCoredump => {
+                Some(Preset::SingleAppLargeCoredump)
+            }
+            Preset::TwoOta | Preset::TwoOtaCoredump => Some(Preset::TwoOtaCoredump),
+            _ => None,
+        }
+    }
+
+    /// The file name of the preset within ESP-IDF's `partition_table`
+    /// component
+    pub fn filename(&self) -> &'static str {
+        match self {
+            Preset::SingleApp => "partitions_singleapp.csv",
+            Preset::SingleAppCoredump => "partitions_singleapp_coredump.csv",
+            Preset::SingleAppEncryptedNvs => "partitions_singleapp_encr_nvs.csv",
+            Preset::SingleAppLarge => "partitions_singleapp_large.csv",
+            Preset::SingleAppLargeCoredump => "partitions_singleapp_large_coredump.csv",
+            Preset::SingleAppLargeEncryptedNvs => "partitions_singleapp_large_encr_nvs.csv",
+            Preset::TwoOta => "partitions_two_ota.csv",
+            Preset::TwoOtaCoredump => "partitions_two_ota_coredump.csv",
+            Preset::TwoOtaEncryptedNvs => "partitions_two_ota_encr_nvs.csv",
+            Preset::TwoOtaLarge => "partitions_two_ota_large.csv",
+        }
+    }
+
+    /// The contents of the preset's CSV file
+    pub fn csv(&self) -> &'static str {
+        match self {
+            Preset::SingleApp => SINGLE_APP,
+            Preset::SingleAppCoredump => SINGLE_APP_COREDUMP,
+            Preset::SingleAppEncryptedNvs => SINGLE_APP_ENCR_NVS,
+            Preset::SingleAppLarge => SINGLE_APP_LARGE,
+            Preset::SingleAppLargeCoredump => SINGLE_APP_LARGE_COREDUMP,
+            Preset::SingleAppLargeEncryptedNvs => SINGLE_APP_LARGE_ENCR_NVS,
+            Preset::TwoOta => TWO_OTA,
+            Preset::TwoOtaCoredump => TWO_OTA_COREDUMP,
+            Preset::TwoOtaEncryptedNvs => TWO_OTA_ENCR_NVS,
+            Preset::TwoOtaLarge => TWO_OTA_LARGE,
+        }
+    }
+
+    /// Construct the partition table, with the partition table itself at
+    /// `table_offset`
+    pub fn table(&self, table_offset: u32) -> Result<PartitionTable, Error> {
+        let ctx = ValidationContext::default().with_table_offset(table_offset);
+        PartitionTable::try_from_str_with(self.csv(), &ctx)
+    }
+}
+
+/// Single factory app, no OTA
+pub fn singleapp(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleApp.table(table_offset)
+}
+
+/// Single factory app, no OTA, with a coredump partition
+pub fn singleapp_coredump(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleAppCoredump.table(table_offset)
+}
+
+/// Single factory app, no OTA, with encrypted NVS
+pub fn singleapp_encr_nvs(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleAppEncryptedNvs.table(table_offset)
+}
+
+/// Single factory app (large), no OTA
+pub fn singleapp_large(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleAppLarge.table(table_offset)
+}
+
+/// Single factory app (large), no OTA, with a coredump partition
+pub fn singleapp_large_coredump(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleAppLargeCoredump.table(table_offset)
+}
+
+/// Single factory app (large), no OTA, with encrypted NVS
+pub fn singleapp_large_encr_nvs(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::SingleAppLargeEncryptedNvs.table(table_offset)
+}
+
+/// Factory app, two OTA definitions
+pub fn two_ota(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::TwoOta.table(table_offset)
+}
+
+/// Factory app, two OTA definitions, with a coredump partition
+pub fn two_ota_coredump(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::TwoOtaCoredump.table(table_offset)
+}
+
+/// Factory app, two OTA definitions, with encrypted NVS
+pub fn two_ota_encr_nvs(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::TwoOtaEncryptedNvs.table(table_offset)
+}
+
+/// Two large OTA definitions, without a factory app
+pub fn two_ota_large(table_offset: u32) -> Result<PartitionTable, Error> {
+    Preset::TwoOtaLarge.table(table_offset)
+}
+
+const SINGLE_APP: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+";
+
+const SINGLE_APP_COREDUMP: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+coredump, data, coredump,,        64K,
+";
+
+const SINGLE_APP_ENCR_NVS: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+nvs_key,  data, nvs_keys,,        0x1000, encrypted
+";
+
+const SINGLE_APP_LARGE: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1500K,
+";
+
+const SINGLE_APP_LARGE_COREDUMP: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1500K,
+coredump, data, coredump,,        64K,
+";
+
+const SINGLE_APP_LARGE_ENCR_NVS: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1500K,
+nvs_key,  data, nvs_keys,,        0x1000, encrypted
+";
+
+const TWO_OTA: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+ota_0,    app,  ota_0,   ,        1M,
+ota_1,    app,  ota_1,   ,        1M,
+";
+
+const TWO_OTA_COREDUMP: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+ota_0,    app,  ota_0,   ,        1M,
+ota_1,    app,  ota_1,   ,        1M,
+coredump, data, coredump,,        64K,
+";
+
+const TWO_OTA_ENCR_NVS: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+ota_0,    app,  ota_0,   ,        1M,
+ota_1,    app,  ota_1,   ,        1M,
+nvs_key,  data, nvs_keys,,        0x1000, encrypted
+";
+
+const TWO_OTA_LARGE: &str = "\
+# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+ota_0,    app,  ota_0,   ,        1700K,
+ota_1,    app,  ota_1,   ,        1700K,
+";
@@ -0,0 +1,94 @@
+use crate::{Partition, PartitionTable, SubType, Type};
+
+/// A query matching partitions by type, subtype and label, with the same
+/// semantics as ESP-IDF's `esp_partition_find`
+///
+/// Each criterion is optional, and an unset criterion matches any partition
+/// (equivalent to `ESP_PARTITION_TYPE_ANY` and `ESP_PARTITION_SUBTYPE_ANY`,
+/// or a `NULL` label).
+///
+/// ```rust
+/// use esp_idf_part::{PartitionTable, Query, Type};
+///
+/// let table = PartitionTable::try_from_str(
+///     "nvs, data, nvs, 0x9000, 0x6000,\n\
+///      ota_0, app, ota_0, 0x10000, 1M,\n\
+///      ota_1, app, ota_1, 0x110000, 1M,",
+/// )
+/// .unwrap();
+///
+/// let apps = table.query(Query::new().with_type(Type::App));
+/// assert_eq!(apps.count(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Query {
+    ty: Option<Type>,
+    subtype: Option<SubType>,
+    label: Option<String>,
+}
+
+impl Query {
+    /// Construct a new query, which matches every partition
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match partitions of the given type
+    pub fn with_type(mut self, ty: Type) -> Self {
+        self.ty = Some(ty);
+        self
+    }
+
+    /// Only match partitions of the given subtype
+    ///
+    /// A [SubType::App] or [SubType::Data] subtype also implies the partition's
+    /// type, whereas a [SubType::Custom] subtype matches partitions of any
+    /// type with the same numeric subtype.
+    pub fn with_subtype<S>(mut self, subtype: S) -> Self
+    where
+        S: Into<SubType>,
+    {
+        self.subtype = Some(subtype.into());
+        self
+    }
+
+    /// Only match the partition with the given label (name)
+    pub fn with_label<S>(mut self, label: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Does the given partition match the query?
+    pub fn matches(&self, partition: &Partition) -> bool {
+        let ty = partition.ty();
+
+        let type_matches = self.ty.is_none_or(|t| t == ty);
+        let subtype_matches = self.subtype.is_none_or(|subtype| {
+            let implied = match subtype {
+                SubType::App(..) => Some(Type::App),
+                SubType::Data(..) => Some(Type::Data),
+                SubType::Custom(..) => None,
+            };
+
+            implied.is_none_or(|t| t == ty)
+                && subtype.canonicalize(ty) == partition.subtype().canonicalize(ty)
+        });
+        let label_matches = self
+            .label
+            .as_deref()
+            .is_none_or(|label| partition.name() == label);
+
+        type_matches && subtype_matches && label_matches
+    }
+}
+
+impl PartitionTable {
+    /// Return an iterator over the partitions matching the query, in table
+    /// order
+    pub fn query(&self, query: Query) -> impl Iterator<Item = &Partition> {
+        self.partitions.iter().filter(move |p| query.matches(p))
+    }
+}
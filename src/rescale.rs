@@ -0,0 +1,219 @@
+use crate::{
+    DataType,
+    Error,
+    FlashSize,
+    Partition,
+    PartitionTable,
+    SizeConstraint,
+    SubType,
+    Type,
+    ValidationContext,
+    partition::DATA_PARTITION_ALIGNMENT,
+};
+
+impl PartitionTable {
+    /// Rescale the partition table to flash of a different size
+    ///
+    /// See [PartitionTable::rescale_with] for more information.
+    pub fn rescale(&self, flash_size: FlashSize) -> Result<PartitionTable, Error> {
+        self.rescale_with(&ValidationContext::default(), flash_size)
+    }
+
+    /// Rescale the partition table to flash of a different size, using the
+    /// provided [ValidationContext]
+    ///
+    /// App partitions and filesystem (`fat`, `spiffs` and `littlefs`)
+    /// partitions are elastic, and grow or shrink in proportion to their
+    /// current size; all other partitions keep their size. See
+    /// [PartitionTable::rescale_by_with] for more information.
+    pub fn rescale_with(
+        &self,
+        ctx: &ValidationContext,
+        flash_size: FlashSize,
+    ) -> Result<PartitionTable, Error> {
+        self.rescale_by_with(ctx, flash_size, |partition| {
+            partition.ty() == Type::App
+                || matches!(
+                    partition.subtype().canonicalize(partition.ty()),
+                    SubType::Data(DataType::Fat | DataType::Spiffs | DataType::Littlefs)
+                )
+        })
+    }
+
+    /// Rescale the partition table to flash of a different size, resizing only
+    /// those partitions for which `is_elastic` returns `true`
+    ///
+    /// See [PartitionTable::rescale_by_with] for more information.
+    pub fn rescale_by<F>(
+        &self,
+        flash_size: FlashSize,
+        is_elastic: F,
+    ) -> Result<PartitionTable, Error>
+    where
+        F: Fn(&Partition) -> bool,
+    {
+        self.rescale_by_with(&ValidationContext::default(), flash_size, is_elastic)
+    }
+
+    /// Rescale the partition table to flash of a different size, resizing only
+    /// those partitions for which `is_elastic` returns `true`, using the
+    /// provided [ValidationContext]
+    ///
+    /// The current flash size is that of the context, or, if it has none, the
+    /// smallest which holds every partition; a layout for 8MB of flash which
+    /// ends at 3MB must therefore be rescaled with a context whose flash size
+    /// is 8MB, lest its free space be given to the elastic partitions.
+    ///
+    /// The difference between the current and the new flash size is divided
+    /// between the elastic partitions in proportion to their current size,
+    /// after which any remaining space is given to the last elastic `data`
+    /// partition. Sizes are kept multiples of the alignment of the partition's
+    /// type, so partitions which were equal in size remain so, and the order of
+    /// and gaps between partitions are preserved.
+    ///
+    /// The size constraints of the partitions are those of the context, and
+    /// the rescaled partition table is validated using it, with its flash size
+    /// replaced by the new flash size.
+    pub fn rescale_by_with<F>(
+        &self,
+        ctx: &ValidationContext,
+        flash_size: FlashSize,
+        is_elastic: F,
+    ) -> Result<PartitionTable, Error>
+    where
+        F: Fn(&Partition) -> bool,
+    {
+        let unsatisfiable = |reason: String| Err(Error::UnsatisfiableLayout(reason));
+
+        let end = self.partitions.iter().map(|p| p.end()).max().unwrap_or(0);
+        let current = match ctx.flash_size() {
+            Some(current) if u64::from(current.bytes()) < end => {
+                return unsatisfiable(format!(
+                    "the partitions end at {end:#x}, which exceeds the {current} flash"
+                ));
+            }
+            Some(current) => current,
+            None => match FlashSize::fitting(end) {
+                Some(current) => current,
+                None => {
+                    return unsatisfiable(format!(
+                        "the partitions end at {end:#x}, which exceeds every supported flash size"
+                    ));
+                }
+            },
+        };
+        let current_end = u64::from(current.bytes());
+
+        let ctx = ctx.clone().with_flash_size(flash_size);
+        let flash_end = u64::from(flash_size.bytes());
+
+        let elastic = self.partitions.iter().map(&is_elastic).collect::<Vec<_>>();
+        let elastic_size = self
+            .partitions
+            .iter()
+            .zip(&elastic)
+            .filter(|(_, elastic)| **elastic)
+            .map(|(p, _)| u64::from(p.size()))
+            .sum::<u64>();
+        if elastic_size == 0 {
+            return unsatisfiable("the partition table has no elastic partitions".into());
+        }
+
+        let Some(available) = (elastic_size + flash_end).checked_sub(current_end) else {
+            return unsatisfiable(format!(
+                "the fixed size partitions do not fit in {flash_size} of flash"
+            ));
+        };
+
+        let mut sizes = Vec::with_capacity(self.partitions.len());
+        for (partition, &elastic) in self.partitions.iter().zip(&elastic) {
+            if !elastic {
+                sizes.push(u64::from(partition.size()));
+                continue;
+            }
+
+            let granularity = u64::from(partition.ty().alignment());
+            let size = u64::from(partition.size()) * available / elastic_size;
+            let size = size - size % granularity;
+
+            let constraint = SizeConstraint::for_type(partition.ty(), partition.subtype(), &ctx);
+            let minimum = constraint
+                .minimum()
+                .map_or(u64::from(DATA_PARTITION_ALIGNMENT), u64::from);
+            if size < minimum {
+                return unsatisfiable(format!(
+                    "partition '{}' would shrink to {size:#x} bytes, but must be at least \
+                     {minimum:#x} bytes",
+                    partition.name()
+                ));
+            }
+
+            let maximum = constraint.maximum().map_or(u64::MAX, u64::from);
+            sizes.push(size.min(maximum - maximum % granularity));
+        }
+
+        // Give any space left over from rounding to the last elastic data partition
+        let last = self
+            .partitions
+            .iter()
+            .zip(&elastic)
+            .rposition(|(p, elastic)| *elastic && p.ty() != Type::App);
+        if let Some(index) = last {
+            let slack = flash_end.saturating_sub(self.place(&sizes).1);
+            let slack = slack - slack % u64::from(DATA_PARTITION_ALIGNMENT);
+
+            sizes[index] += slack;
+            if self.place(&sizes).1 > flash_end {
+                sizes[index] -= slack;
+            }
+        }
+
+        let (offsets, end) = self.place(&sizes);
+        if end > flash_end {
+            return unsatisfiable(format!(
+                "the partitions end at {end:#x} once rescaled, which exceeds {flash_size} of flash"
+            ));
+        }
+
+        let mut table = self.clone();
+        for ((partition, offset), size) in table.partitions.iter_mut().zip(offsets).zip(sizes) {
+            // Both are bounded by the flash size, so always fit
+            partition.set_offset(offset as u32);
+            partition.set_size(size as u32);
+        }
+
+        table.validate_with(&ctx)?;
+
+        Ok(table)
+    }
+
+    /// Place the partitions, in order of their current offsets, with the given
+    /// sizes; returns the new offsets and the end of the last partition
+    fn place(&self, sizes: &[u64]) -> (Vec<u64>, u64) {
+        let mut order = (0..self.partitions.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| self.partitions[index].offset());
+
+        let mut offsets = vec![0; self.partitions.len()];
+        let mut previous_end = None;
+        let mut end = 0;
+
+        for index in order {
+            let partition = &self.partitions[index];
+
+            // Preserve the gap between this partition and the previous one
+            let offset = match previous_end {
+                Some(previous_end) => {
+                    let gap = u64::from(partition.offset()).saturating_sub(previous_end);
+                    (end + gap).next_multiple_of(u64::from(partition.ty().alignment()))
+                }
+                None => u64::from(partition.offset()),
+            };
+
+            offsets[index] = offset;
+            previous_end = Some(partition.end());
+            end = offset + sizes[index];
+        }
+
+        (offsets, end)
+    }
+}
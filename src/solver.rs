@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+
+use crate::{
+    AppType,
+    DataType,
+    Error,
+    Flags,
+    FlashSize,
+    PARTITION_TABLE_SIZE,
+    Partition,
+    PartitionTable,
+    SizeConstraint,
+    SubType,
+    Type,
+    ValidationContext,
+    partition::{DATA_PARTITION_ALIGNMENT, OTADATA_SIZE},
+};
+
+/// How the [LayoutSolver] chooses the size of a partition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Extent {
+    /// The smallest size permitted by the partition's constraints
+    #[default]
+    Minimum,
+    /// Exactly the given number of bytes
+    Fixed(u32),
+    /// The given percentage of the flash size
+    Percentage(u8),
+    /// As much of the remaining space as possible
+    ///
+    /// Remaining space is shared equally between all partitions which fill
+    /// it, subject to their minimum and maximum sizes.
+    Fill,
+}
+
+/// A partition to be laid out by the [LayoutSolver], along with the
+/// constraints on its size
+///
+/// In addition to any minimum or maximum given, each partition is subject to
+/// the hard limits of its [SizeConstraint].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Requirement {
+    name: String,
+    ty: Type,
+    subtype: SubType,
+    flags: Flags,
+    extent: Extent,
+    minimum: Option<u32>,
+    maximum: Option<u32>,
+    group: Option<String>,
+}
+
+impl Requirement {
+    /// Construct a new requirement for a partition of the given type and
+    /// subtype
+    pub fn new<S>(name: S, ty: Type, subtype: SubType) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            ty,
+            subtype,
+            flags: Flags::empty(),
+            extent: Extent::default(),
+            minimum: None,
+            maximum: None,
+            group: None,
+        }
+    }
+
+    /// Construct a new requirement for an `app` partition
+    pub fn app<S>(name: S, subtype: AppType) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::new(name, Type::App, SubType::App(subtype))
+    }
+
+    /// Construct a new requirement for a `data` partition
+    pub fn data<S>(name: S, subtype: DataType) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::new(name, Type::Data, SubType::Data(subtype))
+    }
+
+    /// Set how the size of the partition is chosen
+    pub fn with_extent(mut self, extent: Extent) -> Self {
+        self.extent = extent;
+        self
+    }
+
+    /// Set the smallest acceptable size of the partition
+    pub fn with_minimum(mut self, size: u32) -> Self {
+        self.minimum = Some(size);
+        self
+    }
+
+    /// Set the largest acceptable size of the partition
+    pub fn with_maximum(mut self, size: u32) -> Self {
+        self.maximum = Some(size);
+        self
+    }
+
+    /// Add the partition to a group of partitions which must be equal in size
+    ///
+    /// Only partitions with an [Extent::Fill] extent are affected.
+    pub fn with_group<S>(mut self, group: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.group = Some(group.into());
+        self
+    }
+
+    /// Set the partition's flags
+    pub fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Return the partition's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return how the size of the partition is chosen
+    pub fn extent(&self) -> Extent {
+        self.extent
+    }
+
+    /// The granularity of sizes chosen by the solver, such that the following
+    /// partition need not be padded
+    fn granularity(&self) -> u64 {
+        u64::from(self.ty.alignment().max(DATA_PARTITION_ALIGNMENT))
+    }
+}
+
+/// Solve for a partition table layout which satisfies a set of
+/// [Requirement]s
+///
+/// Partitions are placed in the order they are added, following the partition
+/// table, and aligned as required by their type. Sizes are chosen according to
+/// each partition's [Extent], after which any space remaining is divided
+/// between the partitions which fill it.
+///
+/// ```rust
+/// use esp_idf_part::{
+///     AppType,
+///     DataType,
+///     Extent,
+///     FlashSize,
+///     LayoutSolver,
+///     Requirement,
+///     SizeExt as _,
+/// };
+///
+/// let table = LayoutSolver::new(FlashSize::_8Mb)
+///     .partition(Requirement::data("nvs", DataType::Nvs).with_minimum(24.kib()))
+///     .partition(Requirement::data("otadata", DataType::Ota))
+///     .partition(Requirement::data("phy_init", DataType::Phy))
+///     .partition(
+///         Requirement::app("ota_0", AppType::Ota_0)
+///             .with_extent(Extent::Fill)
+///             .with_group("ota"),
+///     )
+///     .partition(
+///         Requirement::app("ota_1", AppType::Ota_1)
+///             .with_extent(Extent::Fill)
+///             .with_group("ota"),
+///     )
+///     .partition(
+///         Requirement::data("coredump", DataType::Coredump).with_extent(Extent::Fixed(256.kib())),
+///     )
+///     .partition(Requirement::data("storage", DataType::Littlefs).with_extent(Extent::Fill))
+///     .solve()
+///     .unwrap();
+///
+/// let ota_0 = table.find("ota_0").unwrap();
+/// let ota_1 = table.find("ota_1").unwrap();
+/// assert_eq!(ota_0.size(), ota_1.size());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LayoutSolver {
+    ctx: ValidationContext,
+    flash_size: FlashSize,
+    requirements: Vec<Requirement>,
+}
+
+/// The range of sizes a partition, or a group of partitions, may take
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    minimum: u64,
+    maximum: u64,
+    granularity: u64,
+}
+
+impl Bounds {
+    /// The size at the given fill level
+    fn at(&self, level: u64) -> u64 {
+        (level - level % self.granularity).clamp(self.minimum, self.maximum)
+    }
+}
+
+impl LayoutSolver {
+    /// Construct a new solver for flash of the given size
+    pub fn new(flash_size: FlashSize) -> Self {
+        Self {
+            ctx: ValidationContext::default(),
+            flash_size,
+            requirements: Vec::new(),
+        }
+    }
+
+    /// Use the given context to lay out and validate the partition table
+    ///
+    /// The context's flash size is replaced by that of the solver.
+    pub fn with_context(mut self, ctx: ValidationContext) -> Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// Add a partition to the layout, following any previously added
+    pub fn partition(mut self, requirement: Requirement) -> Self {
+        self.requirements.push(requirement);
+        self
+    }
+
+    /// Find a layout satisfying all requirements, and validate the resulting
+    /// partition table
+    ///
+    /// If there is no such layout, [Error::UnsatisfiableLayout] is returned
+    /// explaining why.
+    pub fn solve(&self) -> Result<PartitionTable, Error> {
+        let unsatisfiable = |reason: String| Err(Error::UnsatisfiableLayout(reason));
+
+        let ctx = self.ctx.clone().with_flash_size(self.flash_size);
+        let flash_end = u64::from(self.flash_size.bytes());
+        let start = u64::from(ctx.table_offset()) + u64::from(PARTITION_TABLE_SIZE);
+
+        let mut bounds = Vec::with_capacity(self.requirements.len());
+        for requirement in &self.requirements {
+            bounds.push(self.bounds(requirement, &ctx)?);
+        }
+
+        // Partitions in the same group are bounded by the intersection of their ranges
+        let mut groups = HashMap::<&str, Bounds>::new();
+        for (requirement, bounds) in self.requirements.iter().zip(&bounds) {
+            let Some(group) = requirement.group.as_deref() else {
+                continue;
+            };
+            if requirement.extent != Extent::Fill {
+                continue;
+            }
+
+            let combined = groups.entry(group).or_insert(*bounds);
+            combined.minimum = combined.minimum.max(bounds.minimum);
+            combined.maximum = combined.maximum.min(bounds.maximum);
+            combined.granularity = combined.granularity.max(bounds.granularity);
+        }
+
+        // The combined bounds are aligned to the coarsest granularity in the group, so
+        // that every size chosen suits all of its partitions
+        for (group, bounds) in &mut groups {
+            bounds.minimum = bounds.minimum.next_multiple_of(bounds.granularity);
+            bounds.maximum -= bounds.maximum % bounds.granularity;
+
+            if bounds.minimum > bounds.maximum {
+                return unsatisfiable(format!(
+                    "the partitions in group '{group}' must be equal in size, but no size is \
+                     within the bounds of all of them"
+                ));
+            }
+        }
+
+        for (requirement, bounds) in self.requirements.iter().zip(bounds.iter_mut()) {
+            if requirement.extent != Extent::Fill {
+                continue;
+            }
+
+            if let Some(group) = requirement.group.as_deref().and_then(|g| groups.get(g)) {
+                *bounds = *group;
+            }
+        }
+
+        // Place partitions of the given sizes in order, returning their offsets and the
+        // end of the last partition
+        let place = |sizes: &[u64]| {
+            let mut next = start;
+            let offsets = self
+                .requirements
+                .iter()
+                .zip(sizes)
+                .map(|(requirement, size)| {
+                    let offset = next.next_multiple_of(u64::from(requirement.ty.alignment()));
+                    next = offset + size;
+
+                    offset
+                })
+                .collect::<Vec<_>>();
+
+            (offsets, next)
+        };
+        let sizes_at = |level: u64| bounds.iter().map(|b| b.at(level)).collect::<Vec<_>>();
+
+        let (_, end) = place(&sizes_at(0));
+        if end > flash_end {
+            return unsatisfiable(format!(
+                "the partitions require at least {:#x} bytes following the partition table at \
+                 {:#x}, which exceeds the {} flash by {:#x} bytes",
+                end - start,
+                ctx.table_offset(),
+                self.flash_size,
+                end - flash_end
+            ));
+        }
+
+        // Find the highest fill level at which the partitions still fit
+        let (mut low, mut high) = (0, flash_end);
+        while low < high {
+            let level = low + (high - low).div_ceil(2);
+            if place(&sizes_at(level)).1 <= flash_end {
+                low = level;
+            } else {
+                high = level - 1;
+            }
+        }
+
+        // Rounding each size down to its granularity may leave some space unused, which
+        // is given to the last ungrouped partitions filling the remaining space
+        let mut sizes = sizes_at(low);
+        for (index, requirement) in self.requirements.iter().enumerate().rev() {
+            if requirement.extent != Extent::Fill || requirement.group.is_some() {
+                continue;
+            }
+
+            let slack = flash_end - place(&sizes).1;
+            let bounds = bounds[index];
+            let grown = (sizes[index] + slack - slack % bounds.granularity).min(bounds.maximum);
+
+            let original = std::mem::replace(&mut sizes[index], grown);
+            if place(&sizes).1 > flash_end {
+                sizes[index] = original;
+            }
+        }
+
+        let (offsets, _) = place(&sizes);
+        let partitions = self
+            .requirements
+            .iter()
+            .zip(offsets.into_iter().zip(sizes))
+            .map(|(requirement, (offset, size))| {
+                // Both are bounded by the flash size, so always fit
+                Partition::new(
+                    requirement.name.clone(),
+                    requirement.ty,
+                    requirement.subtype,
+                    offset as u32,
+                    size as u32,
+                    requirement.flags,
+                )
+            })
+            .collect();
+
+        let table = PartitionTable::new(partitions);
+        table.validate_with(&ctx)?;
+
+        Ok(table)
+    }
+
+    fn bounds(&self, requirement: &Requirement, ctx: &ValidationContext) -> Result<Bounds, Error> {
+        let unsatisfiable = |reason: String| Err(Error::UnsatisfiableLayout(reason));
+        let name = &requirement.name;
+
+        let flash_end = u64::from(self.flash_size.bytes());
+        let granularity = requirement.granularity();
+        let constraint = SizeConstraint::for_type(requirement.ty, requirement.subtype, ctx);
+
+        let mut minimum = [requirement.minimum, constraint.minimum()]
+            .into_iter()
+            .flatten()
+            .max()
+            .map_or(0, u64::from);
+        let mut maximum = [requirement.maximum, constraint.maximum()]
+            .into_iter()
+            .flatten()
+            .min()
+            .map_or(flash_end, u64::from)
+            .min(flash_end);
+
+        if requirement.subtype.canonicalize(requirement.ty) == SubType::Data(DataType::Ota) {
+            minimum = minimum.max(u64::from(OTADATA_SIZE));
+            maximum = maximum.min(u64::from(OTADATA_SIZE));
+        }
+
+        if minimum > maximum {
+            return unsatisfiable(format!(
+                "partition '{name}' must be at least {minimum:#x} bytes, but at most {maximum:#x} \
+                 bytes"
+            ));
+        }
+
+        let exact = |size: u64, how: &str| {
+            if size < minimum || size > maximum {
+                unsatisfiable(format!(
+                    "partition '{name}' is {how} of {size:#x} bytes, but must be between \
+                     {minimum:#x} and {maximum:#x} bytes"
+                ))
+            } else {
+                Ok(Bounds {
+                    minimum: size,
+                    maximum: size,
+                    granularity,
+                })
+            }
+        };
+
+        match requirement.extent {
+            Extent::Minimum if minimum == 0 => unsatisfiable(format!(
+                "partition '{name}' has no size; give it a fixed size or a minimum, or let it \
+                 fill the remaining space"
+            )),
+            Extent::Minimum => exact(minimum.next_multiple_of(granularity), "given its minimum"),
+            Extent::Fixed(size) => exact(u64::from(size), "given a fixed size"),
+            Extent::Percentage(percent) if percent > 100 => unsatisfiable(format!(
+                "partition '{name}' is given {percent}% of the flash, which is more than all of it"
+            )),
+            Extent::Percentage(percent) => {
+                let size = flash_end * u64::from(percent) / 100;
+                exact(
+                    size - size % granularity,
+                    &format!("given {percent}% of the flash"),
+                )
+            }
+            Extent::Fill => {
+                let maximum = maximum - maximum % granularity;
+                if minimum.next_multiple_of(granularity) > maximum {
+                    return unsatisfiable(format!(
+                        "partition '{name}' must be at least {minimum:#x} bytes, but at most \
+                         {maximum:#x} bytes once aligned to {granularity:#x}"
+                    ));
+                }
+
+                Ok(Bounds {
+                    minimum: minimum.next_multiple_of(granularity),
+                    maximum,
+                    granularity,
+                })
+            }
+        }
+    }
+}
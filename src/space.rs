@@ -0,0 +1,133 @@
+use crate::{PartitionTable, ValidationContext};
+
+/// A contiguous region of flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    offset: u32,
+    size: u32,
+}
+
+impl Region {
+    /// Construct a new region
+    pub fn new(offset: u32, size: u32) -> Self {
+        Self { offset, size }
+    }
+
+    /// Return the offset of the region
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Return the size of the region
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Return the end of the region, i.e. the offset following its last byte
+    pub fn end(&self) -> u64 {
+        u64::from(self.offset) + u64::from(self.size)
+    }
+
+    /// Does the region contain the given address?
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.offset && u64::from(address) < self.end()
+    }
+}
+
+impl PartitionTable {
+    /// Return an iterator over the regions of flash which are not allocated to
+    /// any partition, in order of their offsets
+    ///
+    /// See [PartitionTable::gaps_with] for more information.
+    pub fn gaps(&self) -> impl DoubleEndedIterator<Item = Region> + use<> {
+        self.gaps_with(&ValidationContext::default())
+    }
+
+    /// Return an iterator over the regions of flash which are not allocated to
+    /// any partition, in order of their offsets
+    ///
+    /// The regions reserved for the bootloader and the partition table, as
+    /// determined by the partition table offset, are never free. If the
+    /// [ValidationContext] specifies a flash size then the last gap ends with
+    /// the flash, otherwise it ends at the 4GB limit of the partition table
+    /// format.
+    pub fn gaps_with(
+        &self,
+        ctx: &ValidationContext,
+    ) -> impl DoubleEndedIterator<Item = Region> + use<> {
+        let mut allocated = ctx
+            .reserved_regions()
+            .map(|(_, region)| (u64::from(region.offset()), region.end()))
+            .into_iter()
+            .chain(
+                self.partitions
+                    .iter()
+                    .map(|p| (u64::from(p.offset()), p.end())),
+            )
+            .collect::<Vec<_>>();
+        allocated.sort();
+
+        let limit = ctx.flash_limit();
+        let mut gaps = Vec::new();
+        let mut cursor = 0;
+
+        for (start, end) in allocated.into_iter().chain([(limit, limit)]) {
+            let start = start.min(limit);
+            if start > cursor {
+                // Both are bounded by the 4GB limit, so always fit
+                gaps.push(Region::new(cursor as u32, (start - cursor) as u32));
+            }
+
+            cursor = cursor.max(end);
+        }
+
+        gaps.into_iter()
+    }
+
+    /// Return the largest region of flash which is not allocated to any
+    /// partition, if any
+    ///
+    /// See [PartitionTable::gaps_with] for more information.
+    pub fn largest_gap(&self) -> Option<Region> {
+        self.largest_gap_with(&ValidationContext::default())
+    }
+
+    /// Return the largest region of flash which is not allocated to any
+    /// partition, if any; the lowest is returned should several be equally
+    /// large
+    ///
+    /// See [PartitionTable::gaps_with] for more information.
+    pub fn largest_gap_with(&self, ctx: &ValidationContext) -> Option<Region> {
+        self.gaps_with(ctx).rev().max_by_key(|region| region.size())
+    }
+
+    /// Find the lowest offset with the given alignment at which a partition of
+    /// the given size would fit, if any
+    ///
+    /// See [PartitionTable::find_free_with] for more information.
+    pub fn find_free(&self, size: u32, alignment: u32) -> Option<u32> {
+        self.find_free_with(&ValidationContext::default(), size, alignment)
+    }
+
+    /// Find the lowest offset with the given alignment at which a partition of
+    /// the given size would fit, if any
+    ///
+    /// Partitions must be aligned as required by their type to pass
+    /// validation; see [Type::alignment]. An `alignment` of `0` is treated as
+    /// `1`.
+    ///
+    /// [Type::alignment]: crate::Type::alignment
+    pub fn find_free_with(
+        &self,
+        ctx: &ValidationContext,
+        size: u32,
+        alignment: u32,
+    ) -> Option<u32> {
+        let alignment = u64::from(alignment.max(1));
+
+        self.gaps_with(ctx).find_map(|gap| {
+            let offset = u64::from(gap.offset()).next_multiple_of(alignment);
+            (offset + u64::from(size) <= gap.end()).then_some(offset as u32)
+        })
+    }
+}
@@ -0,0 +1,212 @@
+use core::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator, VariantNames};
+
+/// Supported Espressif chips
+///
+/// Some validation rules depend on the hardware being targeted, for example
+/// whether or not the chip has a radio which requires PHY calibration data.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Display,
+    EnumIter,
+    EnumString,
+    VariantNames,
+    Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Chip {
+    Esp32,
+    Esp32c2,
+    Esp32c3,
+    Esp32c5,
+    Esp32c6,
+    Esp32c61,
+    Esp32h2,
+    Esp32p4,
+    Esp32s2,
+    Esp32s3,
+}
+
+impl Chip {
+    /// Does the chip have a radio, and therefore use PHY init data?
+    pub fn has_phy(&self) -> bool {
+        !matches!(self, Chip::Esp32p4)
+    }
+
+    /// The flash offset at which the second stage bootloader is written
+    pub fn bootloader_offset(&self) -> u32 {
+        match self {
+            Chip::Esp32 | Chip::Esp32s2 => 0x1000,
+            Chip::Esp32c5 | Chip::Esp32p4 => 0x2000,
+            _ => 0x0,
+        }
+    }
+}
+
+/// Supported flash sizes
+///
+/// Chips such as the ESP32-S3 and ESP32-P4 are available with up to 64MB of
+/// (octal) flash, and ESP-IDF supports up to 128MB.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    VariantNames,
+    Serialize,
+)]
+pub enum FlashSize {
+    #[serde(rename = "1MB")]
+    #[strum(serialize = "1MB")]
+    _1Mb,
+    #[serde(rename = "2MB")]
+    #[strum(serialize = "2MB")]
+    _2Mb,
+    #[serde(rename = "4MB")]
+    #[strum(serialize = "4MB")]
+    _4Mb,
+    #[serde(rename = "8MB")]
+    #[strum(serialize = "8MB")]
+    _8Mb,
+    #[serde(rename = "16MB")]
+    #[strum(serialize = "16MB")]
+    _16Mb,
+    #[serde(rename = "32MB")]
+    #[strum(serialize = "32MB")]
+    _32Mb,
+    #[serde(rename = "64MB")]
+    #[strum(serialize = "64MB")]
+    _64Mb,
+    #[serde(rename = "128MB")]
+    #[strum(serialize = "128MB")]
+    _128Mb,
+}
+
+impl FlashSize {
+    /// Return the size of the flash in bytes
+    pub fn bytes(&self) -> u32 {
+        const MB: u32 = 1024 * 1024;
+
+        match self {
+            FlashSize::_1Mb => MB,
+            FlashSize::_2Mb => 2 * MB,
+            FlashSize::_4Mb => 4 * MB,
+            FlashSize::_8Mb => 8 * MB,
+            FlashSize::_16Mb => 16 * MB,
+            FlashSize::_32Mb => 32 * MB,
+            FlashSize::_64Mb => 64 * MB,
+            FlashSize::_128Mb => 128 * MB,
+        }
+    }
+
+    /// Return the smallest flash size which is at least `bytes` large, if any
+    pub fn fitting(bytes: u64) -> Option<Self> {
+        Self::iter().find(|size| u64::from(size.bytes()) >= bytes)
+    }
+}
+
+impl fmt::Display for FlashSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}MB", self.bytes() / (1024 * 1024))
+    }
+}
+
+/// An ESP-IDF release, used to select version-dependent validation rules
+///
+/// Versions can be parsed from strings such as `v5.3`, `5.3.1` or `v4.4.8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IdfVersion {
+    major: u8,
+    minor: u8,
+    patch: u8,
+}
+
+impl IdfVersion {
+    /// Construct a new ESP-IDF version
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Return the major version number
+    pub fn major(&self) -> u8 {
+        self.major
+    }
+
+    /// Return the minor version number
+    pub fn minor(&self) -> u8 {
+        self.minor
+    }
+
+    /// Return the patch version number
+    pub fn patch(&self) -> u8 {
+        self.patch
+    }
+}
+
+impl fmt::Display for IdfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for IdfVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid ESP-IDF version '{s}'");
+
+        let mut parts = s
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse::<u8>().map_err(|_| invalid()));
+
+        let major = parts.next().ok_or_else(invalid)??;
+        let minor = parts.next().transpose()?.unwrap_or(0);
+        let patch = parts.next().transpose()?.unwrap_or(0);
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_idf_version() {
+        assert_eq!("v5.3".parse(), Ok(IdfVersion::new(5, 3, 0)));
+        assert_eq!("5.3.1".parse(), Ok(IdfVersion::new(5, 3, 1)));
+        assert_eq!("v4".parse(), Ok(IdfVersion::new(4, 0, 0)));
+
+        assert!("".parse::<IdfVersion>().is_err());
+        assert!("v5.x".parse::<IdfVersion>().is_err());
+        assert!("5.3.1.0".parse::<IdfVersion>().is_err());
+
+        assert!(IdfVersion::new(4, 4, 8) < IdfVersion::new(5, 0, 0));
+    }
+}
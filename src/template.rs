@@ -0,0 +1,488 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    Error,
+    Partition,
+    PartitionTable,
+    ValidationContext,
+    partition::{is_record, parse_offset_or_size},
+};
+
+// The columns containing offsets and sizes, in which arithmetic is evaluated
+const OFFSET_COLUMN: usize = 3;
+const SIZE_COLUMN: usize = 4;
+
+// Directives which are recognised, but not supported
+const UNSUPPORTED_DIRECTIVES: &[&str] = &["ifdef", "ifndef", "elif", "else"];
+
+/// A partition table CSV template
+///
+/// Templates extend the CSV format with:
+///
+/// - `${VAR}` substitution, using the variables provided (for example, the
+///   values from `sdkconfig`)
+/// - arithmetic in the offset and size columns, using `+`, `-`, `*`, `/` and
+///   parentheses, e.g. `${APP_SIZE} * 2 + 64K`
+/// - conditional rows, enclosed by `#if FEATURE` (or `#if !FEATURE`) and
+///   `#endif`, which may be nested
+///
+/// A condition holds if the feature has been enabled, or if a variable of the
+/// same name is set to a value other than an empty string, `n`, `0` or `false`.
+///
+/// Directives must be written exactly as above, with the `#` immediately
+/// followed by the directive; a malformed directive (such as `#if` followed
+/// by anything other than a single feature name) is an error rather than a
+/// comment. Ordinary comments are passed through unchanged, without variable
+/// substitution.
+///
+/// ```rust
+/// use esp_idf_part::Template;
+///
+/// let template = Template::new(
+///     "nvs,     data, nvs,     , 24K,\n\
+///      factory, app,  factory, , ${APP_SIZE},\n\
+///      #if ZIGBEE\n\
+///      zb_fct,  data, fat,     , 4K * 4,\n\
+///      #endif",
+/// )
+/// .with_variable("APP_SIZE", "1M");
+///
+/// let csv = template.render().unwrap();
+/// assert_eq!(
+///     csv,
+///     "nvs,     data, nvs,     , 24K,\n\
+///      factory, app,  factory, , 1M,\n"
+/// );
+///
+/// let table = template.with_feature("ZIGBEE").to_table().unwrap();
+/// assert_eq!(table.find("zb_fct").unwrap().size(), 0x4000);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    variables: HashMap<String, String>,
+    features: HashSet<String>,
+}
+
+impl Template {
+    /// Construct a new template from its source
+    pub fn new<S>(source: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            source: source.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the value of a variable
+    pub fn with_variable<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the values of several variables
+    pub fn with_variables<I, K, V>(mut self, variables: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.variables
+            .extend(variables.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Enable a feature, for use in `#if` conditions
+    pub fn with_feature<S>(mut self, feature: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.features.insert(feature.into());
+        self
+    }
+
+    /// Render the template as plain CSV
+    pub fn render(&self) -> Result<String, Error> {
+        Ok(self
+            .render_lines()?
+            .into_iter()
+            .flatten()
+            .map(|line| line + "\n")
+            .collect())
+    }
+
+    /// Render the template and parse the resulting partition table
+    pub fn to_table(&self) -> Result<PartitionTable, Error> {
+        self.to_table_with(&ValidationContext::default())
+    }
+
+    /// Render the template and parse the resulting partition table, using the
+    /// provided [ValidationContext]
+    ///
+    /// Errors in the CSV, and validation errors concerning a particular
+    /// partition, are reported as [Error::TemplateError], pointing to the line
+    /// of the template from which the offending row was rendered.
+    pub fn to_table_with(&self, ctx: &ValidationContext) -> Result<PartitionTable, Error> {
+        let mut csv = String::new();
+        // The template line from which each CSV record was rendered, along with the
+        // record's partition name; blank lines and comments do not produce records
+        let mut records = Vec::new();
+
+        for (index, line) in self.render_lines()?.into_iter().enumerate() {
+            let Some(line) = line else {
+                continue;
+            };
+
+            if is_record(&line) {
+                // Should the row not parse, the CSV error will be reported below
+                let name = parse_row(&line)
+                    .ok()
+                    .and_then(|row| row.get(0).map(|name| name.trim().to_string()))
+                    .unwrap_or_default();
+                records.push((index + 1, name));
+            }
+
+            csv.push_str(&line);
+            csv.push('\n');
+        }
+
+        let table =
+            PartitionTable::parse_csv_with(&csv, ctx).map_err(|e| locate(e, &records, &[], ctx))?;
+        table
+            .validate_with(ctx)
+            .map_err(|e| locate(e, &records, table.partitions(), ctx))?;
+
+        Ok(table)
+    }
+
+    /// Render each line of the template, or `None` if the line is not part of
+    /// the output
+    fn render_lines(&self) -> Result<Vec<Option<String>>, Error> {
+        let mut lines = Vec::new();
+        // The line number and result of each enclosing condition
+        let mut conditions = Vec::<(usize, bool)>::new();
+
+        for (index, line) in self.source.lines().enumerate() {
+            let number = index + 1;
+            let error = |message: String| Error::TemplateError {
+                line: number,
+                message,
+            };
+
+            let trimmed = line.trim();
+            if let Some(directive) = parse_directive(trimmed) {
+                match directive.map_err(error)? {
+                    Directive::If { name, negated } => {
+                        conditions.push((number, self.is_enabled(name) != negated));
+                    }
+                    Directive::Endif => {
+                        conditions
+                            .pop()
+                            .ok_or_else(|| error("'#endif' without a matching '#if'".into()))?;
+                    }
+                }
+
+                lines.push(None);
+                continue;
+            }
+
+            if !conditions.iter().all(|(_, holds)| *holds) {
+                lines.push(None);
+                continue;
+            }
+
+            // Comments are passed through as they are
+            if trimmed.starts_with('#') {
+                lines.push(Some(line.to_string()));
+                continue;
+            }
+
+            let line = self.substitute(line).map_err(error)?;
+            let line = evaluate_columns(&line).map_err(error)?;
+            lines.push(Some(line));
+        }
+
+        if let Some((line, _)) = conditions.pop() {
+            return Err(Error::TemplateError {
+                line,
+                message: "'#if' without a matching '#endif'".into(),
+            });
+        }
+
+        Ok(lines)
+    }
+
+    fn is_enabled(&self, name: &str) -> bool {
+        self.features.contains(name)
+            || self
+                .variables
+                .get(name)
+                .is_some_and(|value| !matches!(value.as_str(), "" | "n" | "0" | "false"))
+    }
+
+    /// Replace each `${VAR}` in the line with the variable's value
+    fn substitute(&self, line: &str) -> Result<String, String> {
+        let mut result = String::with_capacity(line.len());
+        let mut rest = line;
+
+        while let Some(start) = rest.find("${") {
+            result.push_str(&rest[..start]);
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "unterminated '${'".to_string())?;
+            let name = &rest[start + 2..start + end];
+            let value = self
+                .variables
+                .get(name)
+                .ok_or_else(|| format!("undefined variable '{name}'"))?;
+
+            result.push_str(value);
+            rest = &rest[start + end + 1..];
+        }
+
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+/// Report an error in a particular record, or concerning a particular
+/// partition, as an [Error::TemplateError], pointing to the template line of
+/// its record
+///
+/// Errors which do not concern a single partition, or whose partition cannot be
+/// found, are returned unchanged.
+fn locate(
+    error: Error,
+    records: &[(usize, String)],
+    partitions: &[Partition],
+    ctx: &ValidationContext,
+) -> Error {
+    let position = |name: &str| records.iter().position(|(_, n)| n == name);
+
+    let index = match &error {
+        Error::CsvError(e) => e.position().map(|pos| pos.record() as usize),
+        // The second of the two partitions is the one in conflict with the first
+        Error::DuplicatePartitions(name) => {
+            let label = partitions
+                .iter()
+                .find(|p| p.name() == *name)
+                .map(|p| p.label());
+            partitions
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| Some(p.label()) == label)
+                .nth(1)
+                .map(|(index, _)| index)
+        }
+        Error::OverlappingPartitions(_, name)
+        | Error::InvalidPartitionName { name, .. }
+        | Error::PartitionOutOfBounds { name, .. }
+        | Error::PartitionTooLarge(name)
+        | Error::PartitionTooSmall { name, .. }
+        | Error::UnsupportedByIdfVersion { name, .. }
+        | Error::RuleViolation {
+            partition: Some(name),
+            ..
+        } => position(name),
+        // These errors do not name the partition, so find the first which fails
+        Error::UnalignedPartition | Error::InvalidOtadataPartitionSize => partitions
+            .iter()
+            .position(|p| PartitionTable::validate_partition(p, ctx).is_err()),
+        _ => None,
+    };
+
+    match index.and_then(|index| records.get(index)) {
+        Some((line, _)) => Error::TemplateError {
+            line: *line,
+            message: error.to_string(),
+        },
+        None => error,
+    }
+}
+
+/// A conditional directive
+enum Directive<'a> {
+    If { name: &'a str, negated: bool },
+    Endif,
+}
+
+/// Parse a (trimmed) line of the template as a directive, returning `None` if
+/// it is not one
+fn parse_directive(line: &str) -> Option<Result<Directive<'_>, String>> {
+    let rest = line.strip_prefix('#')?;
+    let (keyword, argument) = rest
+        .split_once(char::is_whitespace)
+        .map_or((rest, ""), |(keyword, argument)| (keyword, argument.trim()));
+
+    let directive = match keyword {
+        "if" => {
+            let (name, negated) = match argument.strip_prefix('!') {
+                Some(name) => (name.trim_start(), true),
+                None => (argument, false),
+            };
+
+            let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+            if name.is_empty() || !name.chars().all(is_name) {
+                Err(format!(
+                    "'#if' must be followed by a single feature name, found '{argument}'"
+                ))
+            } else {
+                Ok(Directive::If { name, negated })
+            }
+        }
+        "endif" if argument.is_empty() => Ok(Directive::Endif),
+        "endif" => Err(format!("unexpected '{argument}' after '#endif'")),
+        keyword if UNSUPPORTED_DIRECTIVES.contains(&keyword) => {
+            Err(format!("unsupported directive '#{keyword}'"))
+        }
+        _ => return None,
+    };
+
+    Some(directive)
+}
+
+/// Split a row of CSV into its fields, respecting quoting
+fn parse_row(line: &str) -> Result<csv::StringRecord, String> {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(line.as_bytes())
+        .records()
+        .next()
+        .unwrap_or_else(|| Ok(csv::StringRecord::new()))
+        .map_err(|e| e.to_string())
+}
+
+/// Evaluate any arithmetic in the offset and size columns of a row
+fn evaluate_columns(line: &str) -> Result<String, String> {
+    let mut columns = parse_row(line)?
+        .iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let mut evaluated = false;
+    for index in [OFFSET_COLUMN, SIZE_COLUMN] {
+        let Some(column) = columns.get_mut(index) else {
+            continue;
+        };
+
+        if column.contains(['+', '-', '*', '/', '(', ')']) {
+            let value = Expression::new(column).evaluate()?;
+            *column = format!(" {value:#x}");
+            evaluated = true;
+        }
+    }
+
+    // Rows without arithmetic are left exactly as they were written
+    if !evaluated {
+        return Ok(line.into());
+    }
+
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(vec![]);
+    writer.write_record(&columns).map_err(|e| e.to_string())?;
+    let row = writer.into_inner().map_err(|e| e.to_string())?;
+
+    Ok(String::from_utf8_lossy(&row).trim_end_matches('\n').into())
+}
+
+/// A simple recursive descent parser and evaluator for size expressions
+struct Expression<'a> {
+    input: &'a str,
+}
+
+impl<'a> Expression<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input }
+    }
+
+    fn evaluate(mut self) -> Result<u32, String> {
+        let value = self.sum()?;
+        if !self.input.trim().is_empty() {
+            return Err(format!("unexpected '{}' in expression", self.input.trim()));
+        }
+
+        u32::try_from(value).map_err(|_| "expression exceeds 4GB".into())
+    }
+
+    fn sum(&mut self) -> Result<u64, String> {
+        let mut value = self.product()?;
+
+        loop {
+            if self.consume('+') {
+                value = value
+                    .checked_add(self.product()?)
+                    .ok_or("expression exceeds 4GB")?;
+            } else if self.consume('-') {
+                value = value
+                    .checked_sub(self.product()?)
+                    .ok_or("expression is negative")?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<u64, String> {
+        let mut value = self.term()?;
+
+        loop {
+            if self.consume('*') {
+                value = value
+                    .checked_mul(self.term()?)
+                    .ok_or("expression exceeds 4GB")?;
+            } else if self.consume('/') {
+                value = value
+                    .checked_div(self.term()?)
+                    .ok_or("division by zero in expression")?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<u64, String> {
+        if self.consume('(') {
+            let value = self.sum()?;
+            if !self.consume(')') {
+                return Err("missing ')' in expression".into());
+            }
+
+            return Ok(value);
+        }
+
+        self.input = self.input.trim_start();
+        let len = self
+            .input
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(self.input.len());
+        if len == 0 {
+            return Err("expected a number in expression".into());
+        }
+
+        let (number, rest) = self.input.split_at(len);
+        self.input = rest;
+
+        parse_offset_or_size(number).map(u64::from)
+    }
+
+    fn consume(&mut self, c: char) -> bool {
+        self.input = self.input.trim_start();
+        match self.input.strip_prefix(c) {
+            Some(rest) => {
+                self.input = rest;
+                true
+            }
+            None => false,
+        }
+    }
+}
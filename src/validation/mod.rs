@@ -0,0 +1,350 @@
+use std::sync::Arc;
+
+pub use self::{
+    rule::Rule,
+    secure_boot::SecureBootScheme,
+    size::SizeConstraint,
+    version::{flags_since, subtype_since, type_since},
+};
+use crate::{
+    Chip,
+    DEFAULT_PARTITION_TABLE_OFFSET,
+    Error,
+    FlashSize,
+    IdfVersion,
+    PARTITION_TABLE_SIZE,
+    Partition,
+    Region,
+    partition::MAX_NAME_LEN,
+};
+
+pub(crate) mod rule;
+pub(crate) mod secure_boot;
+pub(crate) mod size;
+pub(crate) mod version;
+
+/// Options which control how a [PartitionTable] is validated
+///
+/// The default context applies the same rules as ESP-IDF's
+/// `gen_esp32part.py`; individual checks may be relaxed or tightened using the
+/// provided `with_*` methods.
+///
+/// [PartitionTable]: crate::PartitionTable
+#[derive(Debug, Clone)]
+pub struct ValidationContext {
+    non_ascii_names: bool,
+    chip: Option<Chip>,
+    flash_size: Option<FlashSize>,
+    idf_version: Option<IdfVersion>,
+    wl_sector_size: Option<u32>,
+    coredump_size: Option<u32>,
+    table_offset: u32,
+    secure_boot: Option<SecureBootScheme>,
+    bootloader_size: Option<u32>,
+    app_size: Option<u32>,
+    rules: Vec<Arc<dyn Rule>>,
+}
+
+impl Default for ValidationContext {
+    fn default() -> Self {
+        Self {
+            non_ascii_names: false,
+            chip: None,
+            flash_size: None,
+            idf_version: None,
+            wl_sector_size: None,
+            coredump_size: None,
+            table_offset: DEFAULT_PARTITION_TABLE_OFFSET,
+            secure_boot: None,
+            bootloader_size: None,
+            app_size: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl ValidationContext {
+    /// Construct a new validation context with the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow partition names to contain non-ASCII (UTF-8) characters
+    ///
+    /// ESP-IDF stores partition names as raw bytes, so any UTF-8 encoded name
+    /// will work on-device, however most tooling assumes ASCII names. Names
+    /// must still fit within the label field once encoded.
+    pub fn with_non_ascii_names(mut self, allow: bool) -> Self {
+        self.non_ascii_names = allow;
+        self
+    }
+
+    /// Are partition names allowed to contain non-ASCII characters?
+    pub fn non_ascii_names(&self) -> bool {
+        self.non_ascii_names
+    }
+
+    /// Set the chip which the partition table is intended for
+    ///
+    /// Checks which depend on the chip are skipped unless it is set: whether
+    /// the Secure Boot scheme is supported, whether the signed bootloader fits
+    /// before the partition table, and whether a PHY init data partition is of
+    /// any use.
+    pub fn with_chip(mut self, chip: Chip) -> Self {
+        self.chip = Some(chip);
+        self
+    }
+
+    /// Return the chip which the partition table is intended for, if known
+    pub fn chip(&self) -> Option<Chip> {
+        self.chip
+    }
+
+    /// Set the size of the flash which the partition table is intended for
+    ///
+    /// When set, every partition must end within the flash. Otherwise, only
+    /// the 32-bit address space is enforced.
+    pub fn with_flash_size(mut self, size: FlashSize) -> Self {
+        self.flash_size = Some(size);
+        self
+    }
+
+    /// Return the size of the flash which the partition table is intended
+    /// for, if known
+    pub fn flash_size(&self) -> Option<FlashSize> {
+        self.flash_size
+    }
+
+    /// Set the version of ESP-IDF which the partition table is intended for
+    ///
+    /// When set, any partition using a subtype or flag which is not supported
+    /// by this version of ESP-IDF is a validation error.
+    pub fn with_idf_version(mut self, version: IdfVersion) -> Self {
+        self.idf_version = Some(version);
+        self
+    }
+
+    /// Return the version of ESP-IDF which the partition table is intended
+    /// for, if known
+    pub fn idf_version(&self) -> Option<IdfVersion> {
+        self.idf_version
+    }
+
+    /// Set the wear levelling sector size (`CONFIG_WL_SECTOR_SIZE`)
+    ///
+    /// When set, FAT partitions which are too small to be formatted are a
+    /// validation error rather than a lint.
+    pub fn with_wl_sector_size(mut self, size: u32) -> Self {
+        self.wl_sector_size = Some(size);
+        self
+    }
+
+    /// Return the wear levelling sector size, if known
+    pub fn wl_sector_size(&self) -> Option<u32> {
+        self.wl_sector_size
+    }
+
+    /// Set the size in bytes of the core dumps produced by the firmware
+    ///
+    /// When set, any `coredump` partition smaller than this is a validation
+    /// error.
+    pub fn with_coredump_size(mut self, size: u32) -> Self {
+        self.coredump_size = Some(size);
+        self
+    }
+
+    /// Return the size of the core dumps produced by the firmware, if known
+    pub fn coredump_size(&self) -> Option<u32> {
+        self.coredump_size
+    }
+
+    /// Set the offset of the partition table (`CONFIG_PARTITION_TABLE_OFFSET`)
+    ///
+    /// When parsing a CSV partition table, partitions without an explicit
+    /// offset are placed following the partition table. Defaults to `0x8000`.
+    pub fn with_table_offset(mut self, offset: u32) -> Self {
+        self.table_offset = offset;
+        self
+    }
+
+    /// Return the offset of the partition table
+    pub fn table_offset(&self) -> u32 {
+        self.table_offset
+    }
+
+    /// Validate the partition table against the layout requirements of the
+    /// given Secure Boot scheme
+    ///
+    /// Signed images are larger than unsigned ones, so the bootloader region
+    /// preceding the partition table and each app partition must leave room
+    /// for their signatures. The checks concerning the bootloader require the
+    /// chip to be set as well, see [ValidationContext::with_chip].
+    pub fn with_secure_boot(mut self, scheme: SecureBootScheme) -> Self {
+        self.secure_boot = Some(scheme);
+        self
+    }
+
+    /// Return the Secure Boot scheme in use, if any
+    pub fn secure_boot(&self) -> Option<SecureBootScheme> {
+        self.secure_boot
+    }
+
+    /// Set the size in bytes of the (unsigned) second stage bootloader
+    ///
+    /// If not set, a typical size for a release build is assumed.
+    pub fn with_bootloader_size(mut self, size: u32) -> Self {
+        self.bootloader_size = Some(size);
+        self
+    }
+
+    /// Return the size of the second stage bootloader, if known
+    pub fn bootloader_size(&self) -> Option<u32> {
+        self.bootloader_size
+    }
+
+    /// Set the size in bytes of the (unsigned) application image
+    ///
+    /// If not set, app partitions are only required to be a multiple of the
+    /// signed image padding.
+    pub fn with_app_size(mut self, size: u32) -> Self {
+        self.app_size = Some(size);
+        self
+    }
+
+    /// Return the size of the application image, if known
+    pub fn app_size(&self) -> Option<u32> {
+        self.app_size
+    }
+
+    /// Register a user-defined [Rule], to be run alongside the built-in checks
+    pub fn with_rule<R>(mut self, rule: R) -> Self
+    where
+        R: Rule + 'static,
+    {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Return an iterator over the registered user-defined rules
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+
+    /// The end of the addressable flash
+    pub(crate) fn flash_limit(&self) -> u64 {
+        // Offsets and sizes are 32-bit, so without knowing the flash size the best we
+        // can do is to ensure partitions do not wrap around the address space
+        self.flash_size()
+            .map_or(1 << 32, |size| u64::from(size.bytes()))
+    }
+
+    /// The regions of flash preceding the first partition, which are reserved
+    /// for the bootloader and the partition table
+    pub(crate) fn reserved_regions(&self) -> [(&'static str, Region); 2] {
+        [
+            ("bootloader", Region::new(0, self.table_offset)),
+            (
+                "partition table",
+                Region::new(self.table_offset, PARTITION_TABLE_SIZE),
+            ),
+        ]
+    }
+}
+
+/// An advisory diagnostic produced by [PartitionTable::lint]
+///
+/// Unlike an [Error], a lint does not prevent the partition table from being
+/// used, but likely indicates a mistake.
+///
+/// [PartitionTable::lint]: crate::PartitionTable::lint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    partition: Option<String>,
+    message: String,
+}
+
+impl Lint {
+    /// Construct a new lint, optionally relating to a specific partition
+    pub fn new<S>(partition: Option<&Partition>, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            partition: partition.map(|p| p.name()),
+            message: message.into(),
+        }
+    }
+
+    /// Return the name of the partition this lint relates to, if any
+    pub fn partition(&self) -> Option<&str> {
+        self.partition.as_deref()
+    }
+
+    /// Return the lint's message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl core::fmt::Display for Lint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match &self.partition {
+            Some(name) => write!(f, "Partition '{name}': {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Ensure that a partition name can be stored in the binary partition table
+///
+/// The label field is [MAX_NAME_LEN] bytes long and ESP-IDF expects it to be
+/// NUL-terminated, so at most `MAX_NAME_LEN - 1` bytes are available.
+pub(crate) fn check_name(name: &str, ctx: &ValidationContext) -> Result<(), Error> {
+    let invalid = |reason: String| Error::InvalidPartitionName {
+        name: name.to_string(),
+        reason,
+    };
+
+    if name.is_empty() {
+        return Err(invalid("the name must not be empty".into()));
+    }
+
+    if name.contains('\0') {
+        return Err(invalid(
+            "the name contains an embedded NUL character".into(),
+        ));
+    }
+
+    if !ctx.non_ascii_names && !name.is_ascii() {
+        return Err(invalid("the name contains non-ASCII characters".into()));
+    }
+
+    if name.chars().any(char::is_control) {
+        return Err(invalid("the name contains non-printable characters".into()));
+    }
+
+    if name.len() >= MAX_NAME_LEN {
+        return Err(invalid(format!(
+            "the name is {} bytes long, but at most {} bytes fit alongside the NUL terminator",
+            name.len(),
+            MAX_NAME_LEN - 1
+        )));
+    }
+
+    Ok(())
+}
+
+/// Ensure that a partition ends within the addressable flash
+pub(crate) fn check_bounds(partition: &Partition, ctx: &ValidationContext) -> Result<(), Error> {
+    let limit = ctx.flash_limit();
+
+    if partition.end() > limit {
+        return Err(Error::PartitionOutOfBounds {
+            name: partition.name(),
+            end: partition.end(),
+            limit,
+        });
+    }
+
+    Ok(())
+}
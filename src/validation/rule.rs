@@ -0,0 +1,66 @@
+use super::{Lint, ValidationContext};
+use crate::{Error, PartitionTable};
+
+/// A user-defined validation rule
+///
+/// Rules are registered with a [ValidationContext] using
+/// [ValidationContext::with_rule], and are run after the built-in checks by
+/// both [PartitionTable::validate_with] and [PartitionTable::lint]. A rule may
+/// return any [Error] variant; [Error::RuleViolation] is provided for rules
+/// which do not correspond to one of the built-in checks.
+///
+/// ```rust
+/// use esp_idf_part::{Error, PartitionTable, Rule, ValidationContext};
+///
+/// struct NoReservedNames;
+///
+/// impl Rule for NoReservedNames {
+///     fn name(&self) -> &str {
+///         "no-reserved-names"
+///     }
+///
+///     fn check(&self, table: &PartitionTable, _ctx: &ValidationContext) -> Result<(), Error> {
+///         match table.find("nvs_keys") {
+///             Some(partition) => Err(Error::RuleViolation {
+///                 rule: self.name().into(),
+///                 partition: Some(partition.name()),
+///                 message: "the name 'nvs_keys' is reserved".into(),
+///             }),
+///             None => Ok(()),
+///         }
+///     }
+/// }
+///
+/// let ctx = ValidationContext::new().with_rule(NoReservedNames);
+/// ```
+pub trait Rule: Send + Sync {
+    /// A short, unique name identifying the rule
+    fn name(&self) -> &str;
+
+    /// Check the partition table, returning an error if it violates the rule
+    fn check(&self, _table: &PartitionTable, _ctx: &ValidationContext) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Check the partition table for likely mistakes which do not make it
+    /// invalid
+    fn lint(&self, _table: &PartitionTable, _ctx: &ValidationContext) -> Vec<Lint> {
+        Vec::new()
+    }
+}
+
+impl core::fmt::Debug for dyn Rule {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_tuple("Rule").field(&self.name()).finish()
+    }
+}
+
+pub(crate) fn check(table: &PartitionTable, ctx: &ValidationContext) -> Result<(), Error> {
+    ctx.rules().try_for_each(|rule| rule.check(table, ctx))
+}
+
+pub(crate) fn lint(table: &PartitionTable, ctx: &ValidationContext, lints: &mut Vec<Lint>) {
+    for rule in ctx.rules() {
+        lints.extend(rule.lint(table, ctx));
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use super::ValidationContext;
+use crate::{Chip, Error, PARTITION_TABLE_SIZE, PartitionTable, Type};
+
+const FLASH_SECTOR_SIZE: u32 = 0x1000; // 4kB
+const MMU_PAGE_SIZE: u32 = 0x10000; // 64kB
+
+// A typical release build of the second stage bootloader with Secure Boot
+// enabled; used when the actual size has not been provided
+const DEFAULT_BOOTLOADER_SIZE: u32 = 0x7000;
+
+/// Secure Boot schemes supported by ESP-IDF
+///
+/// For more information, see the ESP-IDF documentation:
+/// <https://docs.espressif.com/projects/esp-idf/en/latest/esp32/security/secure-boot-v2.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SecureBootScheme {
+    /// Secure Boot V1 (ESP32 only), using an AES digest of the bootloader and
+    /// ECDSA signed apps
+    V1,
+    /// Secure Boot V2 using RSA-PSS signatures
+    V2Rsa,
+    /// Secure Boot V2 using ECDSA signatures
+    V2Ecdsa,
+}
+
+impl SecureBootScheme {
+    /// Is this scheme supported by the given chip?
+    pub fn is_supported_by(&self, chip: Chip) -> bool {
+        match self {
+            SecureBootScheme::V1 => chip == Chip::Esp32,
+            SecureBootScheme::V2Rsa => !matches!(chip, Chip::Esp32c2 | Chip::Esp32c61),
+            SecureBootScheme::V2Ecdsa => matches!(
+                chip,
+                Chip::Esp32c2
+                    | Chip::Esp32c5
+                    | Chip::Esp32c6
+                    | Chip::Esp32c61
+                    | Chip::Esp32h2
+                    | Chip::Esp32p4
+            ),
+        }
+    }
+
+    /// The number of bytes appended to a signed image
+    ///
+    /// Secure Boot V2 appends a signature block occupying a whole flash
+    /// sector. Secure Boot V1 appends a 68 byte signature to apps only, but
+    /// this is padded to a flash sector as well.
+    pub fn signature_size(&self) -> u32 {
+        FLASH_SECTOR_SIZE
+    }
+
+    /// Is the bootloader itself signed (and therefore larger) in flash?
+    fn signs_bootloader(&self) -> bool {
+        // With Secure Boot V1 the bootloader digest is stored in eFuse (or at offset
+        // 0x0 in reflashable mode), rather than being appended to the
+        // bootloader
+        !matches!(self, SecureBootScheme::V1)
+    }
+
+    /// The alignment which signed app images are padded to
+    fn app_padding(&self) -> u32 {
+        match self {
+            // Secure Boot V1 pads to a flash sector
+            SecureBootScheme::V1 => FLASH_SECTOR_SIZE,
+            // Secure Boot V2 pads images such that the signature sector is the last sector
+            // of a 64kB MMU page, see `esptool.py elf2image --secure-pad-v2`
+            SecureBootScheme::V2Rsa | SecureBootScheme::V2Ecdsa => MMU_PAGE_SIZE,
+        }
+    }
+}
+
+impl core::fmt::Display for SecureBootScheme {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SecureBootScheme::V1 => write!(f, "Secure Boot V1"),
+            SecureBootScheme::V2Rsa => write!(f, "Secure Boot V2 (RSA)"),
+            SecureBootScheme::V2Ecdsa => write!(f, "Secure Boot V2 (ECDSA)"),
+        }
+    }
+}
+
+pub(crate) fn check(
+    table: &PartitionTable,
+    ctx: &ValidationContext,
+    scheme: SecureBootScheme,
+) -> Result<(), Error> {
+    let invalid = |reason: String| Err(Error::InvalidSecureBootLayout(reason));
+
+    // The partition table must start on a sector boundary, following the bootloader
+    let table_offset = ctx.table_offset();
+    if table_offset % FLASH_SECTOR_SIZE != 0 {
+        return invalid(format!(
+            "the partition table offset {table_offset:#x} is not aligned to {FLASH_SECTOR_SIZE:#x}"
+        ));
+    }
+
+    // Which schemes are supported, and where the bootloader is written, depend on
+    // the chip; without one these checks are skipped
+    if let Some(chip) = ctx.chip() {
+        if !scheme.is_supported_by(chip) {
+            return invalid(format!("{scheme} is not supported by the {chip}"));
+        }
+
+        let bootloader_offset = chip.bootloader_offset();
+        let available = table_offset.saturating_sub(bootloader_offset);
+
+        let mut required = u64::from(ctx.bootloader_size().unwrap_or(DEFAULT_BOOTLOADER_SIZE))
+            .next_multiple_of(u64::from(FLASH_SECTOR_SIZE));
+        if scheme.signs_bootloader() {
+            required += u64::from(scheme.signature_size());
+        }
+
+        if u64::from(available) < required {
+            return invalid(format!(
+                "the signed bootloader requires {required:#x} bytes, but only {available:#x} \
+                 bytes are available between {bootloader_offset:#x} and the partition table \
+                 at {table_offset:#x}; consider increasing the partition table offset"
+            ));
+        }
+    }
+
+    // Moving the partition table must not cause it to collide with any partitions
+    let table_end = u64::from(table_offset) + u64::from(PARTITION_TABLE_SIZE);
+    if let Some(partition) = table
+        .partitions()
+        .iter()
+        .find(|p| u64::from(p.offset()) < table_end)
+    {
+        return invalid(format!(
+            "partition '{}' at {:#x} overlaps the bootloader or the partition table at \
+             {table_offset:#x}",
+            partition.name(),
+            partition.offset()
+        ));
+    }
+
+    // Each app partition must have room for the padded image and its signature
+    for partition in table.partitions().iter().filter(|p| p.ty() == Type::App) {
+        let fits = match ctx.app_size() {
+            Some(size) => {
+                let signed = (u64::from(size) + u64::from(scheme.signature_size()))
+                    .next_multiple_of(u64::from(scheme.app_padding()));
+
+                signed <= u64::from(partition.size())
+            }
+            None => partition.size() % scheme.app_padding() == 0,
+        };
+
+        if !fits {
+            return invalid(format!(
+                "app partition '{}' ({:#x} bytes) does not leave room for the {} signature \
+                 sector",
+                partition.name(),
+                partition.size(),
+                scheme
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use super::{Lint, ValidationContext};
+use crate::{DataType, Error, IdfVersion, Partition, PartitionTable, SubType, Type};
+
+const FLASH_SECTOR_SIZE: u32 = 0x1000; // 4kB
+
+// NVS stores data in pages of one flash sector each; the library always keeps
+// one page free for garbage collection, so fewer than three pages cannot hold
+// data reliably. The default tables shipped with ESP-IDF use 0x6000 (24kB).
+const NVS_MIN_SIZE: u32 = 3 * FLASH_SECTOR_SIZE;
+const NVS_RECOMMENDED_SIZE: u32 = 0x6000;
+
+// The PHY init data blob is exactly one sector
+const PHY_INIT_DATA_SIZE: u32 = FLASH_SECTOR_SIZE;
+
+// The NVS encryption keys are stored in a single sector
+const NVS_KEYS_SIZE: u32 = FLASH_SECTOR_SIZE;
+
+// FatFs refuses to format a volume with fewer than 128 sectors (see `f_mkfs`
+// in components/fatfs/src/ff.c), and the wear levelling layer reserves two
+// state sectors, a config sector and a spare sector on top of this (see
+// `WL_Flash::config` in components/wear_levelling/WL_Flash.cpp)
+const FAT_MIN_SECTORS: u32 = 128;
+const WL_RESERVED_SECTORS: u32 = 4;
+const WL_DEFAULT_SECTOR_SIZE: u32 = 0x1000;
+
+// ELF formatted core dumps became the default in ESP-IDF v5.0, and are
+// considerably larger than the older binary format
+const COREDUMP_ELF_DEFAULT_SINCE: IdfVersion = IdfVersion::new(5, 0, 0);
+const COREDUMP_RECOMMENDED_SIZE_ELF: u32 = 0x10000; // 64kB
+const COREDUMP_RECOMMENDED_SIZE_BIN: u32 = 0x8000; // 32kB
+
+const MAX_APP_PART_SIZE: u32 = 0x100_0000; // 16MB
+
+/// Size constraints for partitions of a given [Type] and [SubType]
+///
+/// Exceeding a hard limit (`minimum` or `maximum`) is a validation error, as
+/// the partition is unusable by ESP-IDF. The recommended limits are advisory,
+/// and are reported as [Lint]s.
+///
+/// Some limits depend on the [ValidationContext], for example the FAT minimum
+/// is only a hard limit once the wear levelling sector size is known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeConstraint {
+    minimum: Option<u32>,
+    maximum: Option<u32>,
+    recommended_minimum: Option<u32>,
+    recommended_maximum: Option<u32>,
+    reason: &'static str,
+}
+
+impl SizeConstraint {
+    /// Look up the size constraints for the given type and subtype
+    ///
+    /// Partitions of custom types, and numerically given subtypes which do not
+    /// belong to the type, are unconstrained.
+    pub fn for_type(ty: Type, subtype: SubType, ctx: &ValidationContext) -> Self {
+        match (ty, subtype.canonicalize(ty)) {
+            (Type::App, SubType::App(_)) => Self::for_app(),
+            (Type::Data, SubType::Data(ty)) => Self::for_data(ty, ctx),
+            _ => Self::default(),
+        }
+    }
+
+    fn for_app() -> Self {
+        // See:
+        // https://github.com/espressif/esp-idf/blob/c212305/components/bootloader_support/src/esp_image_format.c#L158-L161
+        Self {
+            maximum: Some(MAX_APP_PART_SIZE),
+            reason: "app images cannot exceed 16MB",
+            ..Self::default()
+        }
+    }
+
+    fn for_data(ty: DataType, ctx: &ValidationContext) -> Self {
+        match ty {
+            DataType::Nvs => Self {
+                minimum: Some(NVS_MIN_SIZE),
+                recommended_minimum: Some(NVS_RECOMMENDED_SIZE),
+                reason: "NVS requires at least three 4kB pages",
+                ..Self::default()
+            },
+            DataType::Phy => Self {
+                minimum: Some(PHY_INIT_DATA_SIZE),
+                recommended_maximum: Some(PHY_INIT_DATA_SIZE),
+                reason: "the PHY init data is exactly 4kB",
+                ..Self::default()
+            },
+            DataType::NvsKeys => Self {
+                minimum: Some(NVS_KEYS_SIZE),
+                recommended_maximum: Some(NVS_KEYS_SIZE),
+                reason: "the NVS encryption keys occupy a single 4kB sector",
+                ..Self::default()
+            },
+            DataType::Fat => {
+                let sector_size = ctx.wl_sector_size().unwrap_or(WL_DEFAULT_SECTOR_SIZE);
+                let size = FAT_MIN_SECTORS * sector_size + WL_RESERVED_SECTORS * FLASH_SECTOR_SIZE;
+
+                // Without knowing the configured sector size we can only warn
+                if ctx.wl_sector_size().is_some() {
+                    Self {
+                        minimum: Some(size),
+                        reason: "FatFs requires 128 sectors plus the wear levelling sectors",
+                        ..Self::default()
+                    }
+                } else {
+                    Self {
+                        recommended_minimum: Some(size),
+                        reason: "FatFs requires 128 sectors plus the wear levelling sectors",
+                        ..Self::default()
+                    }
+                }
+            }
+            DataType::Coredump => {
+                let recommended = match ctx.idf_version() {
+                    Some(version) if version < COREDUMP_ELF_DEFAULT_SINCE => {
+                        COREDUMP_RECOMMENDED_SIZE_BIN
+                    }
+                    _ => COREDUMP_RECOMMENDED_SIZE_ELF,
+                };
+
+                Self {
+                    minimum: ctx.coredump_size(),
+                    recommended_minimum: Some(recommended),
+                    reason: "the partition must be able to hold a complete core dump",
+                    ..Self::default()
+                }
+            }
+            _ => Self::default(),
+        }
+    }
+
+    /// The smallest size which is usable, if any
+    pub fn minimum(&self) -> Option<u32> {
+        self.minimum
+    }
+
+    /// The largest size which is usable, if any
+    pub fn maximum(&self) -> Option<u32> {
+        self.maximum
+    }
+
+    /// The smallest size which is recommended, if any
+    pub fn recommended_minimum(&self) -> Option<u32> {
+        self.recommended_minimum
+    }
+
+    /// The largest size which is recommended, if any
+    pub fn recommended_maximum(&self) -> Option<u32> {
+        self.recommended_maximum
+    }
+
+    /// A short explanation of where the limits come from
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+
+pub(crate) fn check(table: &PartitionTable, ctx: &ValidationContext) -> Result<(), Error> {
+    for partition in table.partitions() {
+        let constraint = SizeConstraint::for_type(partition.ty(), partition.subtype(), ctx);
+
+        if constraint.maximum.is_some_and(|max| partition.size() > max) {
+            return Err(Error::PartitionTooLarge(partition.name()));
+        }
+
+        if let Some(minimum) = constraint.minimum.filter(|&min| partition.size() < min) {
+            return Err(Error::PartitionTooSmall {
+                name: partition.name(),
+                size: partition.size(),
+                minimum,
+                reason: constraint.reason.into(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn lint(table: &PartitionTable, ctx: &ValidationContext, lints: &mut Vec<Lint>) {
+    for partition in table.partitions() {
+        let constraint = SizeConstraint::for_type(partition.ty(), partition.subtype(), ctx);
+
+        if let Some(minimum) = constraint
+            .recommended_minimum
+            .filter(|&min| partition.size() < min)
+        {
+            lints.push(Lint::new(
+                Some(partition),
+                format!(
+                    "size {:#x} is smaller than the recommended minimum of {:#x}; {}",
+                    partition.size(),
+                    minimum,
+                    constraint.reason
+                ),
+            ));
+        }
+
+        if let Some(maximum) = constraint
+            .recommended_maximum
+            .filter(|&max| partition.size() > max)
+        {
+            lints.push(Lint::new(
+                Some(partition),
+                format!(
+                    "size {:#x} is larger than the recommended maximum of {:#x}; {}, the \
+                     remainder is unused",
+                    partition.size(),
+                    maximum,
+                    constraint.reason
+                ),
+            ));
+        }
+
+        if let Some(chip) = ctx
+            .chip()
+            .filter(|chip| is_phy(partition) && !chip.has_phy())
+        {
+            lints.push(Lint::new(
+                Some(partition),
+                format!("the {chip} has no radio, so PHY init data is never used"),
+            ));
+        }
+    }
+}
+
+fn is_phy(partition: &Partition) -> bool {
+    partition.ty() == Type::Data
+        && partition.subtype().canonicalize(Type::Data) == SubType::Data(DataType::Phy)
+}
@@ -0,0 +1,80 @@
+use super::ValidationContext;
+use crate::{DataType, Error, Flags, IdfVersion, Partition, SubType, Type};
+
+/// Return the earliest ESP-IDF release which supports the given type, if it
+/// has not always been supported
+///
+/// Custom types from `0x40` onwards have always been left to the application,
+/// but ESP-IDF v5.3 gave a meaning to the reserved type IDs `0x02`
+/// (`ESP_PARTITION_TYPE_BOOTLOADER`) and `0x03`
+/// (`ESP_PARTITION_TYPE_PARTITION_TABLE`), which earlier releases ignore.
+pub fn type_since(ty: Type) -> Option<IdfVersion> {
+    match ty {
+        Type::Custom(0x02 | 0x03) => Some(IdfVersion::new(5, 3, 0)),
+        _ => None,
+    }
+}
+
+/// Return the earliest ESP-IDF release which supports the given subtype, if
+/// it has not always been supported
+///
+/// Older releases of the bootloader and `esp_partition` silently ignore (or
+/// mishandle) partitions with a subtype they do not know about. Each version
+/// is the first release whose `esp_partition_subtype_t` (in `esp_partition.h`)
+/// and `gen_esp32part.py` include the subtype.
+pub fn subtype_since(subtype: SubType) -> Option<IdfVersion> {
+    match subtype {
+        // ESP_PARTITION_SUBTYPE_DATA_COREDUMP, added in v3.0
+        SubType::Data(DataType::Coredump) => Some(IdfVersion::new(3, 0, 0)),
+        // ESP_PARTITION_SUBTYPE_DATA_NVS_KEYS, added in v4.0
+        SubType::Data(DataType::NvsKeys) => Some(IdfVersion::new(4, 0, 0)),
+        // ESP_PARTITION_SUBTYPE_DATA_EFUSE_EM, added in v4.0
+        SubType::Data(DataType::EfuseEm) => Some(IdfVersion::new(4, 0, 0)),
+        // ESP_PARTITION_SUBTYPE_DATA_UNDEFINED, added in v5.1
+        SubType::Data(DataType::Undefined) => Some(IdfVersion::new(5, 1, 0)),
+        // ESP_PARTITION_SUBTYPE_DATA_LITTLEFS, added in v5.2
+        SubType::Data(DataType::Littlefs) => Some(IdfVersion::new(5, 2, 0)),
+        _ => None,
+    }
+}
+
+/// Return the earliest ESP-IDF release which supports the given flags, if they
+/// have not always been supported
+pub fn flags_since(flags: Flags) -> Option<IdfVersion> {
+    if flags.contains(Flags::READONLY) {
+        Some(IdfVersion::new(5, 3, 0))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn check(partition: &Partition, ctx: &ValidationContext) -> Result<(), Error> {
+    let Some(target) = ctx.idf_version() else {
+        return Ok(());
+    };
+
+    let unsupported = |feature: String, required: IdfVersion| {
+        Err(Error::UnsupportedByIdfVersion {
+            name: partition.name(),
+            feature,
+            required,
+            target,
+        })
+    };
+
+    let ty = partition.ty();
+    if let Some(required) = type_since(ty).filter(|&required| target < required) {
+        return unsupported(format!("type '{ty}'"), required);
+    }
+
+    let subtype = partition.subtype().canonicalize(ty);
+    if let Some(required) = subtype_since(subtype).filter(|&required| target < required) {
+        return unsupported(format!("subtype '{subtype}'"), required);
+    }
+
+    if let Some(required) = flags_since(partition.flags()).filter(|&required| target < required) {
+        return unsupported("the 'readonly' flag".into(), required);
+    }
+
+    Ok(())
+}
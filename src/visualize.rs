@@ -0,0 +1,345 @@
+use core::fmt::Write as _;
+
+use crate::{
+    AppType,
+    DataType,
+    PartitionTable,
+    SubType,
+    Type,
+    ValidationContext,
+    map::{Segment, segments},
+    partition::format_size,
+};
+
+// The height of the flash map, which blocks are scaled to
+const MAP_HEIGHT: f64 = 720.0;
+const BLOCK_WIDTH: f64 = 160.0;
+const LABEL_WIDTH: f64 = 260.0;
+const LABEL_HEIGHT: f64 = 14.0;
+const COLUMN_GAP: f64 = 40.0;
+const MARGIN: f64 = 20.0;
+const TITLE_HEIGHT: f64 = 30.0;
+const LEGEND_WIDTH: f64 = 180.0;
+const LEGEND_HEIGHT: f64 = 18.0;
+
+/// A graphical map of one or two partition tables' layouts in flash, which
+/// can be exported as standalone SVG or HTML
+///
+/// Each partition is drawn as a block whose position and height are
+/// proportional to its offset and size, coloured by its type and subtype and
+/// labelled with its name, offset and size. Free space and the regions
+/// reserved for the bootloader and the partition table are marked as well.
+///
+/// When a second table is given using [Visualization::with_comparison], the
+/// two are drawn side by side to the same scale.
+///
+/// ```rust
+/// use esp_idf_part::{PartitionTable, Visualization};
+///
+/// let before = PartitionTable::try_from_str("factory, app, factory, , 1M,").unwrap();
+/// let after = PartitionTable::try_from_str("factory, app, factory, , 2M,").unwrap();
+///
+/// let svg = Visualization::new(&before)
+///     .with_title("Before")
+///     .with_comparison("After", &after)
+///     .to_svg();
+/// assert!(svg.starts_with("<svg"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Visualization<'a> {
+    columns: Vec<(String, &'a PartitionTable)>,
+    ctx: ValidationContext,
+}
+
+impl<'a> Visualization<'a> {
+    /// Construct a new visualization of the partition table
+    pub fn new(table: &'a PartitionTable) -> Self {
+        Self {
+            columns: vec![("Partition table".into(), table)],
+            ctx: ValidationContext::default(),
+        }
+    }
+
+    /// Use the provided [ValidationContext]
+    ///
+    /// The map is scaled to the flash size of the context, or else to the
+    /// smallest flash size which fits the partition tables. The reserved
+    /// regions are determined by the partition table offset.
+    pub fn with_context(mut self, ctx: &ValidationContext) -> Self {
+        self.ctx = ctx.clone();
+        self
+    }
+
+    /// Set the title shown above the partition table
+    pub fn with_title<S>(mut self, title: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.columns[0].0 = title.into();
+        self
+    }
+
+    /// Show a second partition table alongside the first, for comparison
+    pub fn with_comparison<S>(mut self, title: S, table: &'a PartitionTable) -> Self
+    where
+        S: Into<String>,
+    {
+        self.columns.truncate(1);
+        self.columns.push((title.into(), table));
+        self
+    }
+
+    /// Render the visualization as a standalone SVG document
+    pub fn to_svg(&self) -> String {
+        // All tables are drawn to the same scale, so that they can be compared
+        let flash_size = self.ctx.flash_size().or_else(|| {
+            self.columns
+                .iter()
+                .filter_map(|(_, table)| segments(table, &self.ctx).0)
+                .max()
+        });
+        let ctx = match flash_size {
+            Some(size) => self.ctx.clone().with_flash_size(size),
+            None => self.ctx.clone(),
+        };
+        let scale = flash_size.map_or(1u64 << 32, |size| u64::from(size.bytes())) as f64;
+        let flash = flash_size.map_or("4GB address space".into(), |size| size.to_string());
+
+        let columns = self.columns.len() as f64;
+        let width =
+            2.0 * MARGIN + columns * (BLOCK_WIDTH + LABEL_WIDTH) + (columns - 1.0) * COLUMN_GAP;
+        let top = MARGIN + TITLE_HEIGHT;
+
+        let mut body = String::new();
+        let mut legend = Vec::<(String, &str)>::new();
+        let mut bottom = top + MAP_HEIGHT;
+
+        for (i, (title, table)) in self.columns.iter().enumerate() {
+            let x = MARGIN + i as f64 * (BLOCK_WIDTH + LABEL_WIDTH + COLUMN_GAP);
+
+            let _ = writeln!(
+                body,
+                r#"<text x="{x:.1}" y="{:.1}" font-size="14" font-weight="bold">{} ({flash})</text>"#,
+                MARGIN + 16.0,
+                escape(title),
+            );
+
+            // Labels are placed beside their blocks, but pushed down where they would
+            // otherwise overlap the previous label
+            let mut previous = top - LABEL_HEIGHT;
+
+            for segment in segments(table, &ctx).1 {
+                let size = segment.end() - u64::from(segment.offset());
+                let y = top + f64::from(segment.offset()) / scale * MAP_HEIGHT;
+                let height = (size as f64 / scale * MAP_HEIGHT).max(1.0);
+
+                let style = Style::of(&segment);
+                if !legend.iter().any(|(label, _)| *label == style.legend) {
+                    legend.push((style.legend.clone(), style.fill));
+                }
+
+                let _ = writeln!(
+                    body,
+                    r#"<rect x="{x:.1}" y="{y:.1}" width="{BLOCK_WIDTH:.1}" height="{height:.1}" fill="{}" stroke="{}"{}><title>{}</title></rect>"#,
+                    style.fill,
+                    style.stroke,
+                    style.dash,
+                    escape(&style.description),
+                );
+
+                let middle = y + height / 2.0;
+                let baseline = (middle + 4.0).max(previous + LABEL_HEIGHT);
+                previous = baseline;
+                bottom = bottom.max(baseline);
+
+                let _ = writeln!(
+                    body,
+                    r##"<line x1="{:.1}" y1="{middle:.1}" x2="{:.1}" y2="{:.1}" stroke="#7f7f7f"/>"##,
+                    x + BLOCK_WIDTH,
+                    x + BLOCK_WIDTH + 8.0,
+                    baseline - 4.0,
+                );
+                let _ = writeln!(
+                    body,
+                    r#"<text x="{:.1}" y="{baseline:.1}">{:#08x}  {}  {}</text>"#,
+                    x + BLOCK_WIDTH + 12.0,
+                    segment.offset(),
+                    escape(&segment.label()),
+                    format_size(size),
+                );
+            }
+
+            let _ = writeln!(
+                body,
+                r#"<rect x="{x:.1}" y="{top:.1}" width="{BLOCK_WIDTH:.1}" height="{MAP_HEIGHT:.1}" fill="none" stroke="black"/>"#,
+            );
+        }
+
+        // A legend of the colours used, below the map
+        let per_row = ((width - 2.0 * MARGIN) / LEGEND_WIDTH).floor().max(1.0) as usize;
+        let legend_top = bottom + 2.0 * MARGIN;
+
+        for (i, (label, fill)) in legend.iter().enumerate() {
+            let x = MARGIN + (i % per_row) as f64 * LEGEND_WIDTH;
+            let y = legend_top + (i / per_row) as f64 * LEGEND_HEIGHT;
+
+            let _ = writeln!(
+                body,
+                r#"<rect x="{x:.1}" y="{:.1}" width="12" height="12" fill="{fill}" stroke="black"/>"#,
+                y - 10.0,
+            );
+            let _ = writeln!(
+                body,
+                r#"<text x="{:.1}" y="{y:.1}">{}</text>"#,
+                x + 18.0,
+                escape(label),
+            );
+        }
+
+        let rows = legend.len().div_ceil(per_row) as f64;
+        let height = legend_top + rows * LEGEND_HEIGHT + MARGIN;
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width:.0}\" height=\"{height:.0}\" \
+             viewBox=\"0 0 {width:.0} {height:.0}\" font-family=\"monospace\" font-size=\"12\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+             {body}</svg>\n"
+        )
+    }
+
+    /// Render the visualization as a standalone HTML document, containing
+    /// the SVG
+    pub fn to_html(&self) -> String {
+        let title = self
+            .columns
+            .iter()
+            .map(|(title, _)| escape(title))
+            .collect::<Vec<_>>()
+            .join(" / ");
+
+        format!(
+            "<!DOCTYPE html>\n\
+             <html lang=\"en\">\n\
+             <head>\n\
+             <meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n\
+             </head>\n\
+             <body>\n\
+             {}</body>\n\
+             </html>\n",
+            self.to_svg()
+        )
+    }
+}
+
+impl PartitionTable {
+    /// Render the partition table's layout in flash as a standalone SVG
+    /// document
+    ///
+    /// See [Visualization] for more options.
+    pub fn to_svg(&self) -> String {
+        Visualization::new(self).to_svg()
+    }
+
+    /// Render the partition table's layout in flash as a standalone HTML
+    /// document
+    ///
+    /// See [Visualization] for more options.
+    pub fn to_html(&self) -> String {
+        Visualization::new(self).to_html()
+    }
+}
+
+/// How a segment of flash is drawn
+struct Style {
+    fill: &'static str,
+    stroke: &'static str,
+    dash: &'static str,
+    legend: String,
+    description: String,
+}
+
+impl Style {
+    fn of(segment: &Segment) -> Self {
+        let size = segment.end() - u64::from(segment.offset());
+        let location = format!("at {:#x} ({})", segment.offset(), format_size(size));
+
+        match segment {
+            Segment::Reserved(name, _) => Self {
+                fill: "#bfbfbf",
+                stroke: "#7f7f7f",
+                dash: "",
+                legend: "reserved".into(),
+                description: format!("{name} {location}"),
+            },
+            Segment::Free(_) => Self {
+                fill: "#ffffff",
+                stroke: "#7f7f7f",
+                dash: r#" stroke-dasharray="4 2""#,
+                legend: "free".into(),
+                description: format!("free space {location}"),
+            },
+            Segment::Partition(partition) => {
+                let ty = partition.ty();
+                let subtype = partition.subtype().canonicalize(ty);
+
+                let fill = match (ty, subtype) {
+                    (Type::App, SubType::App(AppType::Factory)) => "#4e79a7",
+                    (Type::App, SubType::App(AppType::Test)) => "#76b7b2",
+                    (Type::App, SubType::App(..)) => "#59a14f",
+                    (Type::App, _) => "#86bcb6",
+                    (Type::Data, SubType::Data(DataType::Ota)) => "#edc948",
+                    (Type::Data, SubType::Data(DataType::Phy)) => "#b07aa1",
+                    (Type::Data, SubType::Data(DataType::Nvs)) => "#f28e2b",
+                    (Type::Data, SubType::Data(DataType::NvsKeys)) => "#ff9da7",
+                    (Type::Data, SubType::Data(DataType::Coredump)) => "#e15759",
+                    (Type::Data, SubType::Data(DataType::EfuseEm)) => "#bab0ac",
+                    (Type::Data, SubType::Data(DataType::Undefined)) => "#a0cbe8",
+                    (Type::Data, SubType::Data(DataType::Esphttpd)) => "#d37295",
+                    (Type::Data, SubType::Data(DataType::Fat)) => "#9c755f",
+                    (Type::Data, SubType::Data(DataType::Spiffs)) => "#c49c94",
+                    (Type::Data, SubType::Data(DataType::Littlefs)) => "#8c6d31",
+                    (Type::Data, _) => "#d4a6c8",
+                    (Type::Custom(..), _) => "#8cd17d",
+                };
+
+                // Partitions which share a colour share a single legend entry: OTA app
+                // slots, custom subtypes of each type, and custom types
+                let legend = match (ty, subtype) {
+                    (Type::App, SubType::App(AppType::Factory | AppType::Test)) => {
+                        format!("{ty}, {subtype}")
+                    }
+                    (Type::App, SubType::App(..)) => format!("{ty}, ota"),
+                    (Type::App | Type::Data, SubType::Custom(..)) => format!("{ty}, custom"),
+                    (Type::Custom(..), _) => "custom".into(),
+                    _ => format!("{ty}, {subtype}"),
+                };
+
+                Self {
+                    fill,
+                    stroke: "black",
+                    dash: "",
+                    legend,
+                    description: format!("{}: {ty}, {subtype} {location}", partition.name()),
+                }
+            }
+        }
+    }
+}
+
+/// Escape text for use in XML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
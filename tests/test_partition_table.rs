@@ -1,6 +1,39 @@
 use std::fs;
 
-use esp_idf_part::{AppType, Error, Flags, Partition, PartitionTable, SubType, Type};
+use esp_idf_part::{
+    APP_PARTITION_ALIGNMENT,
+    AppType,
+    Chip,
+    DATA_PARTITION_ALIGNMENT,
+    DataType,
+    Error,
+    Extent,
+    FieldChange,
+    Flags,
+    FlashSize,
+    IdfVersion,
+    LayoutSolver,
+    Lint,
+    MigrationStatus,
+    Overlay,
+    Partition,
+    PartitionTable,
+    PartitionTableBuilder,
+    PartitionTableDiff,
+    Query,
+    Region,
+    Requirement,
+    Rule,
+    SecureBootScheme,
+    Size,
+    SizeConstraint,
+    SizeExt as _,
+    SubType,
+    Template,
+    Type,
+    ValidationContext,
+    Visualization,
+};
 
 #[test]
 fn test_parse_bin() {
@@ -220,6 +253,7 @@ fn test_error_when_partitions_overlapping() -> Result<(), String> {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn test_empty_offsets_are_correctly_calculated() {
     let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota.csv").unwrap();
     let table = PartitionTable::try_from(csv).unwrap();
@@ -258,3 +292,2129 @@ fn test_maximum_partition_size_is_enforced() -> Result<(), String> {
         )),
     }
 }
+
+#[test]
+fn test_error_when_partition_name_too_long() -> Result<(), String> {
+    let csv = fs::read_to_string("tests/data/err_partition_name_too_long.csv").unwrap();
+
+    match PartitionTable::try_from_str(csv) {
+        Err(Error::InvalidPartitionName { name, .. }) if name == "areallylongpartitionname" => {
+            Ok(())
+        }
+        result => Err(format!(
+            "expected `Err(Error::InvalidPartitionName {{ .. }})`, found `{result:?}`"
+        )),
+    }
+}
+
+#[test]
+fn test_partition_name_validation() {
+    let partition = |name: &str| {
+        PartitionTable::new(vec![Partition::new(
+            name,
+            Type::App,
+            SubType::App(AppType::Factory),
+            0x10000,
+            0x100000,
+            Flags::empty(),
+        )])
+    };
+
+    // 15 bytes plus the NUL terminator fills the label field exactly
+    assert!(partition("fifteen_bytes_x").validate().is_ok());
+    assert!(matches!(
+        partition("sixteen_bytes_xx").validate(),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+
+    assert!(matches!(
+        partition("").validate(),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+    assert!(matches!(
+        partition("fact\0ry").validate(),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+    assert!(matches!(
+        partition("fact\try").validate(),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+
+    // Non-ASCII names must be explicitly opted into, and are measured in bytes
+    let ctx = ValidationContext::new().with_non_ascii_names(true);
+    assert!(matches!(
+        partition("données").validate(),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+    assert!(partition("données").validate_with(&ctx).is_ok());
+    assert!(matches!(
+        partition("ðððððððð").validate_with(&ctx),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+}
+
+#[test]
+fn test_error_when_nvs_too_small() -> Result<(), String> {
+    let csv = fs::read_to_string("tests/data/err_nvs_too_small.csv").unwrap();
+
+    match PartitionTable::try_from_str(csv) {
+        Err(Error::PartitionTooSmall { name, minimum, .. })
+            if name == "nvs" && minimum == 0x3000 =>
+        {
+            Ok(())
+        }
+        result => Err(format!(
+            "expected `Err(Error::PartitionTooSmall {{ .. }})`, found `{result:?}`"
+        )),
+    }
+}
+
+#[test]
+fn test_size_constraints_depend_on_context() {
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota_2m.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    // With the default 4kB wear levelling sectors the 128kB FAT partition is only
+    // flagged as a lint, until the sector size is actually known
+    let lints = table.lint(&ValidationContext::new());
+    assert!(
+        lints
+            .iter()
+            .any(|lint| lint.partition() == Some("flash_test"))
+    );
+
+    let ctx = ValidationContext::new().with_wl_sector_size(4096);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::PartitionTooSmall { name, .. }) if name == "flash_test"
+    ));
+
+    let ctx = ValidationContext::new().with_wl_sector_size(512);
+    assert!(table.validate_with(&ctx).is_ok());
+
+    // Constraints depend on the type as well as the subtype, so a custom partition
+    // whose subtype happens to share a value with 'fat' is unconstrained
+    let fat = SizeConstraint::for_type(Type::Data, SubType::Data(DataType::Fat), &ctx);
+    assert!(fat.minimum().is_some());
+    let custom = SizeConstraint::for_type(Type::Custom(0x40), SubType::Data(DataType::Fat), &ctx);
+    assert_eq!(custom.minimum(), None);
+
+    // The ESP32-P4 has no radio, so the 'phy_init' partition is pointless
+    let lints = table.lint(&ValidationContext::new().with_chip(Chip::Esp32p4));
+    assert!(
+        lints
+            .iter()
+            .any(|lint| lint.partition() == Some("phy_init"))
+    );
+    let lints = table.lint(&ValidationContext::new().with_chip(Chip::Esp32));
+    assert!(
+        !lints
+            .iter()
+            .any(|lint| lint.partition() == Some("phy_init"))
+    );
+}
+
+#[test]
+fn test_coredump_size_constraint() {
+    let table = PartitionTable::new(vec![
+        Partition::new(
+            "factory",
+            Type::App,
+            SubType::App(AppType::Factory),
+            0x10000,
+            0x100000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "coredump",
+            Type::Data,
+            SubType::Data(DataType::Coredump),
+            0x110000,
+            0x8000,
+            Flags::empty(),
+        ),
+    ]);
+
+    assert!(table.validate().is_ok());
+    assert_eq!(table.lint(&ValidationContext::new()).len(), 1);
+    assert!(
+        table
+            .lint(&ValidationContext::new().with_idf_version("v4.4".parse().unwrap()))
+            .is_empty()
+    );
+
+    let ctx = ValidationContext::new().with_coredump_size(0x10000);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::PartitionTooSmall { name, .. }) if name == "coredump"
+    ));
+}
+
+#[test]
+fn test_secure_boot_layout() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    // On the ESP32 the bootloader starts at 0x1000, so with the default table
+    // offset there is no room for the signature block
+    let ctx = ValidationContext::new()
+        .with_chip(Chip::Esp32)
+        .with_secure_boot(SecureBootScheme::V2Rsa);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::InvalidSecureBootLayout(..))
+    ));
+
+    // Secure Boot V1 does not append a signature to the bootloader
+    let ctx = ValidationContext::new()
+        .with_chip(Chip::Esp32)
+        .with_secure_boot(SecureBootScheme::V1);
+    assert!(table.validate_with(&ctx).is_ok());
+
+    // Moving the partition table requires the partitions to move as well
+    let ctx = ctx.with_table_offset(0x10000);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::InvalidSecureBootLayout(..))
+    ));
+
+    // ECDSA signatures are not supported on the ESP32-S3
+    let ctx = ValidationContext::new()
+        .with_chip(Chip::Esp32s3)
+        .with_secure_boot(SecureBootScheme::V2Ecdsa);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::InvalidSecureBootLayout(..))
+    ));
+
+    // The 1MB app partitions can hold a signed image only if it leaves room for the
+    // signature sector once padded
+    let ctx = ValidationContext::new()
+        .with_chip(Chip::Esp32c3)
+        .with_secure_boot(SecureBootScheme::V2Rsa);
+    assert!(table.validate_with(&ctx).is_ok());
+    assert!(
+        table
+            .validate_with(&ctx.clone().with_app_size(0x100000 - 0x1000))
+            .is_ok()
+    );
+    assert!(matches!(
+        table.validate_with(&ctx.with_app_size(0x100000 - 0x800)),
+        Err(Error::InvalidSecureBootLayout(..))
+    ));
+
+    // Without a chip, the checks which depend on it are skipped, but the others
+    // still apply
+    let ctx = ValidationContext::new().with_secure_boot(SecureBootScheme::V2Ecdsa);
+    assert!(table.validate_with(&ctx).is_ok());
+    assert!(matches!(
+        table.validate_with(&ctx.with_table_offset(0x10000)),
+        Err(Error::InvalidSecureBootLayout(..))
+    ));
+}
+
+#[test]
+fn test_table_offset_is_used_for_auto_placement() {
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota.csv").unwrap();
+    let ctx = ValidationContext::new().with_table_offset(0xC000);
+    let table = PartitionTable::try_from_str_with(csv, &ctx).unwrap();
+
+    assert_eq!(table.find("nvs").unwrap().offset(), 0xD000);
+}
+
+#[test]
+fn test_normalize() {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let hash = |table: &PartitionTable| {
+        let mut hasher = DefaultHasher::new();
+        table.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let canonical = PartitionTable::try_from_str(csv).unwrap();
+    assert!(canonical.is_normalized());
+    let lints = canonical.lint(&ValidationContext::new()).len();
+
+    // Reverse the order, use a numeric subtype, and set a flag which has no effect
+    // on app partitions
+    let mut partitions = canonical.partitions().clone();
+    partitions.reverse();
+    partitions[5] = Partition::new(
+        "nvs",
+        Type::Data,
+        SubType::Custom(0x02),
+        0x9000,
+        0x4000,
+        Flags::empty(),
+    );
+    partitions[0] = Partition::new(
+        "ota_1",
+        Type::App,
+        SubType::App(AppType::Ota_1),
+        0x210000,
+        0x100000,
+        Flags::ENCRYPTED,
+    );
+
+    let mut table = PartitionTable::new(partitions);
+    assert!(table.validate().is_ok());
+    assert!(!table.is_normalized());
+    assert_ne!(table, canonical);
+    assert_eq!(table.lint(&ValidationContext::new()).len(), lints + 3);
+
+    table.normalize();
+    assert_eq!(table, canonical);
+    assert_eq!(hash(&table), hash(&canonical));
+}
+
+#[test]
+fn test_normalize_places_unplaced_partitions() {
+    let mut table = PartitionTable::new(vec![
+        Partition::new(
+            "nvs",
+            Type::Data,
+            SubType::Data(DataType::Nvs),
+            0,
+            0x6000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "phy_init",
+            Type::Data,
+            SubType::Data(DataType::Phy),
+            0,
+            0x1000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "factory",
+            Type::App,
+            SubType::App(AppType::Factory),
+            0,
+            0x100000,
+            Flags::empty(),
+        ),
+    ]);
+    let mut relocated = table.clone();
+    table.normalize();
+
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    assert_eq!(table, PartitionTable::try_from_str(&csv).unwrap());
+
+    // Partitions follow the partition table wherever it has been moved to
+    let ctx = ValidationContext::new().with_table_offset(0xA000);
+    relocated.normalize_with(&ctx);
+    assert!(relocated.is_normalized_with(&ctx));
+    assert_eq!(relocated.find("nvs").unwrap().offset(), 0xB000);
+    assert_eq!(relocated.find("factory").unwrap().offset(), 0x20000);
+}
+
+#[test]
+fn test_readonly_custom_partition_is_kept() {
+    let mut table = PartitionTable::new(vec![
+        Partition::new(
+            "factory",
+            Type::App,
+            SubType::App(AppType::Factory),
+            0x10000,
+            0x100000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "calib",
+            Type::Custom(0x40),
+            SubType::Custom(0x02),
+            0x110000,
+            0x1000,
+            Flags::READONLY,
+        ),
+    ]);
+
+    let calib = table.find("calib").unwrap();
+    assert_eq!(calib.effective_flags(), Flags::READONLY);
+    assert!(table.lint(&ValidationContext::new()).is_empty());
+
+    table.normalize();
+    assert_eq!(table.find("calib").unwrap().flags(), Flags::READONLY);
+}
+
+#[test]
+fn test_large_flash_partition_tables() {
+    let csv = fs::read_to_string("tests/data/large_flash_32MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    let storage = table.find("storage").unwrap();
+    assert_eq!(storage.end(), 32 * 1024 * 1024);
+
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_32Mb);
+    assert!(table.validate_with(&ctx).is_ok());
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_16Mb);
+    assert!(matches!(
+        table.validate_with(&ctx),
+        Err(Error::PartitionOutOfBounds { name, end, limit })
+            if name == "ota_1" && end == 0x1020000 && limit == 0x1000000
+    ));
+
+    let csv = fs::read_to_string("tests/data/large_flash_64MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert_eq!(table.find("storage").unwrap().end(), 64 * 1024 * 1024);
+    assert_eq!(
+        FlashSize::fitting(table.find("storage").unwrap().end()),
+        Some(FlashSize::_64Mb)
+    );
+
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_64Mb);
+    assert!(table.validate_with(&ctx).is_ok());
+}
+
+#[test]
+fn test_partition_end_does_not_overflow() {
+    let partition = |name: &str, offset: u32, size: u32| {
+        Partition::new(
+            name,
+            Type::Data,
+            SubType::Data(DataType::Spiffs),
+            offset,
+            size,
+            Flags::empty(),
+        )
+    };
+    let factory = Partition::new(
+        "factory",
+        Type::App,
+        SubType::App(AppType::Factory),
+        0x10000,
+        0x100000,
+        Flags::empty(),
+    );
+
+    // A partition ending at exactly 4GB is representable
+    let top = partition("top", 0xFFFF_0000, 0x10000);
+    assert_eq!(top.end(), 1 << 32);
+    assert!(!top.overlaps(&factory));
+    assert!(top.overlaps(&partition("below", 0xFFFE_0000, 0x20000)));
+
+    let table = PartitionTable::new(vec![factory.clone(), top]);
+    assert!(table.validate().is_ok());
+
+    // ...but one ending beyond it is not
+    let table = PartitionTable::new(vec![factory, partition("beyond", 0xFFFF_0000, 0x20000)]);
+    assert!(matches!(
+        table.validate(),
+        Err(Error::PartitionOutOfBounds { name, .. }) if name == "beyond"
+    ));
+
+    // Automatically placed partitions must not wrap around either
+    let csv = "factory, app, factory, 0xFFFE0000, 0x10000\nstorage, data, spiffs, , 0x20000\nfoo, data, spiffs, , 0x1000";
+    assert!(matches!(
+        PartitionTable::try_from_str(csv),
+        Err(Error::PartitionOutOfBounds { name, .. }) if name == "foo"
+    ));
+}
+
+#[test]
+fn test_idf_version_support() {
+    let csv = fs::read_to_string("tests/data/large_flash_32MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let ctx = |version: &str| ValidationContext::new().with_idf_version(version.parse().unwrap());
+
+    assert!(table.validate_with(&ctx("v5.3")).is_ok());
+    assert!(matches!(
+        table.validate_with(&ctx("v4.4")),
+        Err(Error::UnsupportedByIdfVersion { name, required, .. })
+            if name == "storage" && required == IdfVersion::new(5, 2, 0)
+    ));
+
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_app.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert!(table.validate_with(&ctx("v4.0")).is_ok());
+    assert!(matches!(
+        table.validate_with(&ctx("v3.3")),
+        Err(Error::UnsupportedByIdfVersion { name, .. }) if name == "nvs_key"
+    ));
+
+    let table = PartitionTable::new(vec![
+        Partition::new(
+            "factory",
+            Type::App,
+            SubType::App(AppType::Factory),
+            0x10000,
+            0x100000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "fctry",
+            Type::Data,
+            SubType::Data(DataType::Nvs),
+            0x110000,
+            0x6000,
+            Flags::READONLY,
+        ),
+    ]);
+    assert!(table.validate_with(&ctx("v5.3.1")).is_ok());
+    assert!(matches!(
+        table.validate_with(&ctx("v5.2")),
+        Err(Error::UnsupportedByIdfVersion { name, .. }) if name == "fctry"
+    ));
+
+    // Custom types are gated where ESP-IDF has since given the type ID a meaning
+    let table = PartitionTable::new(vec![
+        Partition::new(
+            "factory",
+            Type::App,
+            SubType::App(AppType::Factory),
+            0x10000,
+            0x100000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "custom",
+            Type::Custom(0x40),
+            SubType::Custom(0x00),
+            0x110000,
+            0x1000,
+            Flags::empty(),
+        ),
+        Partition::new(
+            "bootloader",
+            Type::Custom(0x02),
+            SubType::Custom(0x00),
+            0x120000,
+            0x1000,
+            Flags::empty(),
+        ),
+    ]);
+    assert!(table.validate_with(&ctx("v5.3")).is_ok());
+    assert!(matches!(
+        table.validate_with(&ctx("v5.2")),
+        Err(Error::UnsupportedByIdfVersion { name, required, .. })
+            if name == "bootloader" && required == IdfVersion::new(5, 3, 0)
+    ));
+}
+
+#[test]
+fn test_user_defined_rules() {
+    // Every product has a factory NVS partition at a fixed offset
+    struct FactoryNvs;
+
+    impl Rule for FactoryNvs {
+        fn name(&self) -> &str {
+            "factory-nvs"
+        }
+
+        fn check(&self, table: &PartitionTable, _ctx: &ValidationContext) -> Result<(), Error> {
+            match table.find("fctry") {
+                Some(p) if p.subtype() == SubType::Data(DataType::Nvs) && p.offset() == 0x9000 => {
+                    Ok(())
+                }
+                _ => Err(Error::RuleViolation {
+                    rule: self.name().into(),
+                    partition: Some("fctry".into()),
+                    message: "an NVS partition must exist at 0x9000".into(),
+                }),
+            }
+        }
+    }
+
+    // The coredump partition should be the last partition
+    struct CoredumpLast;
+
+    impl Rule for CoredumpLast {
+        fn name(&self) -> &str {
+            "coredump-last"
+        }
+
+        fn lint(&self, table: &PartitionTable, _ctx: &ValidationContext) -> Vec<Lint> {
+            let last = table.partitions().iter().max_by_key(|p| p.offset());
+
+            match table.find_by_subtype(Type::Data, SubType::Data(DataType::Coredump)) {
+                Some(coredump) if Some(coredump) != last => {
+                    vec![Lint::new(Some(coredump), "should be the last partition")]
+                }
+                _ => vec![],
+            }
+        }
+    }
+
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    let ctx = ValidationContext::new().with_rule(FactoryNvs);
+
+    match table.validate_with(&ctx) {
+        Err(err @ Error::RuleViolation { .. }) => assert_eq!(
+            err.to_string(),
+            "Rule 'factory-nvs' violated: an NVS partition must exist at 0x9000"
+        ),
+        result => panic!("expected `Err(Error::RuleViolation {{ .. }})`, found `{result:?}`"),
+    }
+
+    let mut partitions = table.partitions().clone();
+    partitions[0] = Partition::new(
+        "fctry",
+        Type::Data,
+        SubType::Data(DataType::Nvs),
+        0x9000,
+        0x4000,
+        Flags::empty(),
+    );
+    partitions.push(Partition::new(
+        "coredump",
+        Type::Data,
+        SubType::Data(DataType::Coredump),
+        0x310000,
+        0x10000,
+        Flags::empty(),
+    ));
+    partitions.push(Partition::new(
+        "storage",
+        Type::Data,
+        SubType::Data(DataType::Spiffs),
+        0x320000,
+        0xE0000,
+        Flags::empty(),
+    ));
+    let table = PartitionTable::new(partitions);
+    assert!(table.validate_with(&ctx).is_ok());
+
+    let ctx = ctx.with_rule(CoredumpLast);
+    let lints = table.lint(&ctx);
+    assert!(lints.iter().any(|lint| lint.partition() == Some("coredump")
+        && lint.message() == "should be the last partition"));
+}
+
+#[test]
+fn test_edit_partition_table() {
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    let mut table = PartitionTable::try_from_str(csv).unwrap();
+
+    table
+        .insert(Partition::new(
+            "storage",
+            Type::Data,
+            SubType::Data(DataType::Fat),
+            0x110000,
+            0x100000,
+            Flags::empty(),
+        ))
+        .unwrap();
+    table.rename("storage", "user_fs").unwrap();
+    table.resize("user_fs", 0x80000).unwrap();
+    table.move_to("user_fs", 0x200000).unwrap();
+    table.set_flags("user_fs", Flags::ENCRYPTED).unwrap();
+
+    let user_fs = table.find("user_fs").unwrap();
+    assert_eq!(user_fs.offset(), 0x200000);
+    assert_eq!(user_fs.size(), 0x80000);
+    assert_eq!(user_fs.flags(), Flags::ENCRYPTED);
+    assert_eq!(table.partitions().last(), Some(user_fs));
+
+    // Moving a partition keeps the table sorted by offset
+    table.move_to("user_fs", 0x1000).unwrap_err();
+    table.resize("nvs", 0x4000).unwrap();
+    table.move_to("user_fs", 0xD000).unwrap_err();
+    table.resize("user_fs", 0x2000).unwrap();
+    table.move_to("user_fs", 0xD000).unwrap();
+    assert_eq!(table.partitions()[1].name(), "user_fs");
+
+    // The written table round-trips
+    let csv = table.to_csv().unwrap();
+    assert_eq!(PartitionTable::try_from_str(csv).unwrap(), table);
+
+    let removed = table.remove("user_fs").unwrap();
+    assert_eq!(removed.name(), "user_fs");
+    assert!(table.find("user_fs").is_none());
+}
+
+#[test]
+fn test_invalid_edits_are_rolled_back() {
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    let mut table = PartitionTable::try_from_str(csv).unwrap();
+    let original = table.clone();
+
+    assert!(matches!(
+        table.rename("missing", "foo"),
+        Err(Error::PartitionNotFound(name)) if name == "missing"
+    ));
+    assert!(matches!(
+        table.rename("nvs", "phy_init"),
+        Err(Error::DuplicatePartitions(..))
+    ));
+    assert!(matches!(
+        table.resize("nvs", 0x7000),
+        Err(Error::OverlappingPartitions(..))
+    ));
+    assert!(matches!(
+        table.move_to("factory", 0x18000),
+        Err(Error::UnalignedPartition)
+    ));
+    assert!(matches!(
+        table.remove("factory"),
+        Err(Error::NoAppPartition)
+    ));
+    assert!(matches!(
+        table.insert(Partition::new(
+            "otadata",
+            Type::Data,
+            SubType::Data(DataType::Ota),
+            0x110000,
+            0x1000,
+            Flags::empty(),
+        )),
+        Err(Error::InvalidOtadataPartitionSize)
+    ));
+
+    assert_eq!(table, original);
+}
+
+#[test]
+fn test_edit_with_validation_context() {
+    let ctx = ValidationContext::new()
+        .with_flash_size(FlashSize::_2Mb)
+        .with_non_ascii_names(true);
+
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    let mut table = PartitionTable::try_from_str_with(csv, &ctx).unwrap();
+
+    // Edits which the context forbids are rejected
+    table.resize("factory", 0x200000).unwrap();
+    assert!(matches!(
+        table.resize_with(&ctx, "factory", 0x300000),
+        Err(Error::PartitionOutOfBounds { .. })
+    ));
+    table.resize_with(&ctx, "factory", 0x100000).unwrap();
+
+    // Edits which are only valid under the context are accepted
+    assert!(matches!(
+        table.rename("nvs", "nvs_ü"),
+        Err(Error::InvalidPartitionName { .. })
+    ));
+    table.rename_with(&ctx, "nvs", "nvs_ü").unwrap();
+
+    let ctx = ctx.with_table_offset(0xA000);
+    assert!(table.move_to_with(&ctx, "nvs_ü", 0xA000).is_err());
+    assert_eq!(table.find("nvs_ü").unwrap().offset(), 0x9000);
+}
+
+#[test]
+fn test_build_partition_table() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let expected = PartitionTable::try_from_str(csv).unwrap();
+
+    let table = PartitionTableBuilder::new()
+        .nvs("nvs", 16.kib())
+        .otadata()
+        .phy_init()
+        .factory(1.mib())
+        .app_ota(2, 1.mib())
+        .build()
+        .unwrap();
+    assert_eq!(table, expected);
+
+    // Partitions following the fill partition are placed at the end of flash
+    let table = PartitionTableBuilder::new()
+        .with_table_offset(0x10000)
+        .with_flash_size(FlashSize::_8Mb)
+        .nvs("nvs", 24.kib())
+        .app("factory", AppType::Factory, 2.mib())
+        .data("storage", DataType::Littlefs, Size::Fill)
+        .data("coredump", DataType::Coredump, 64.kib())
+        .build()
+        .unwrap();
+
+    let offsets = table
+        .partitions()
+        .iter()
+        .map(|p| (p.offset(), p.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        offsets,
+        [
+            (0x11000, 0x6000),
+            (0x20000, 0x200000),
+            (0x220000, 0x5D0000),
+            (0x7F0000, 0x10000),
+        ]
+    );
+}
+
+#[test]
+fn test_build_unsatisfiable_partition_table() {
+    let builder =
+        PartitionTableBuilder::new()
+            .factory(1.mib())
+            .data("storage", DataType::Fat, Size::Fill);
+    assert!(matches!(
+        builder.build(),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+
+    let builder =
+        builder
+            .with_flash_size(FlashSize::_4Mb)
+            .data("spiffs", DataType::Spiffs, Size::Fill);
+    assert!(matches!(
+        builder.build(),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+
+    let builder = PartitionTableBuilder::new()
+        .with_flash_size(FlashSize::_2Mb)
+        .app_ota(2, 1.mib());
+    assert!(matches!(
+        builder.build(),
+        Err(Error::PartitionOutOfBounds { .. })
+    ));
+
+    let builder = PartitionTableBuilder::new()
+        .with_flash_size(FlashSize::_16Mb)
+        .app_ota(17, 64.kib());
+    assert!(matches!(
+        builder.build(),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+}
+
+#[test]
+fn test_solve_layout() {
+    let table = LayoutSolver::new(FlashSize::_8Mb)
+        .partition(Requirement::data("nvs", DataType::Nvs).with_minimum(24.kib()))
+        .partition(Requirement::data("otadata", DataType::Ota))
+        .partition(Requirement::data("phy_init", DataType::Phy))
+        .partition(
+            Requirement::app("ota_0", AppType::Ota_0)
+                .with_extent(Extent::Fill)
+                .with_maximum(3.mib())
+                .with_group("ota"),
+        )
+        .partition(
+            Requirement::app("ota_1", AppType::Ota_1)
+                .with_extent(Extent::Fill)
+                .with_group("ota"),
+        )
+        .partition(
+            Requirement::data("coredump", DataType::Coredump).with_extent(Extent::Fixed(256.kib())),
+        )
+        .partition(Requirement::data("storage", DataType::Littlefs).with_extent(Extent::Fill))
+        .solve()
+        .unwrap();
+
+    let layout = table
+        .partitions()
+        .iter()
+        .map(|p| (p.name(), p.offset(), p.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        layout,
+        [
+            ("nvs".into(), 0x9000, 0x6000),
+            ("otadata".into(), 0xF000, 0x2000),
+            ("phy_init".into(), 0x11000, 0x1000),
+            ("ota_0".into(), 0x20000, 0x280000),
+            ("ota_1".into(), 0x2A0000, 0x280000),
+            ("coredump".into(), 0x520000, 0x40000),
+            ("storage".into(), 0x560000, 0x2A0000),
+        ]
+    );
+
+    // A maximum limits how much of the remaining space is taken
+    let table = LayoutSolver::new(FlashSize::_4Mb)
+        .partition(Requirement::data("nvs", DataType::Nvs).with_extent(Extent::Percentage(1)))
+        .partition(
+            Requirement::app("factory", AppType::Factory)
+                .with_extent(Extent::Fill)
+                .with_maximum(1.mib()),
+        )
+        .solve()
+        .unwrap();
+    assert_eq!(table.find("nvs").unwrap().size(), 0xA000);
+    assert_eq!(table.find("factory").unwrap().size(), 0x100000);
+}
+
+#[test]
+fn test_solve_layout_groups() {
+    // Only filling partitions share the size of their group
+    let table = LayoutSolver::new(FlashSize::_4Mb)
+        .partition(Requirement::data("nvs", DataType::Nvs).with_minimum(24.kib()))
+        .partition(
+            Requirement::app("ota_0", AppType::Ota_0)
+                .with_extent(Extent::Fill)
+                .with_group("ota"),
+        )
+        .partition(
+            Requirement::app("ota_1", AppType::Ota_1)
+                .with_extent(Extent::Fixed(1.mib()))
+                .with_group("ota"),
+        )
+        .solve()
+        .unwrap();
+    assert_eq!(table.find("ota_1").unwrap().size(), 0x100000);
+    assert_eq!(table.find("ota_0").unwrap().size(), 0x2F0000);
+
+    // Sizes shared by app and data partitions suit the coarser app alignment
+    let table = LayoutSolver::new(FlashSize::_4Mb)
+        .partition(Requirement::data("nvs", DataType::Nvs).with_minimum(24.kib()))
+        .partition(
+            Requirement::app("factory", AppType::Factory)
+                .with_extent(Extent::Fill)
+                .with_group("shared"),
+        )
+        .partition(
+            Requirement::data("storage", DataType::Littlefs)
+                .with_extent(Extent::Fill)
+                .with_maximum(0x118000)
+                .with_group("shared"),
+        )
+        .solve()
+        .unwrap();
+    let factory = table.find("factory").unwrap();
+    let storage = table.find("storage").unwrap();
+    assert_eq!(factory.size(), 0x110000);
+    assert_eq!(storage.size(), 0x110000);
+}
+
+#[test]
+fn test_solve_unsatisfiable_layout() {
+    let solver = LayoutSolver::new(FlashSize::_2Mb)
+        .partition(Requirement::data("nvs", DataType::Nvs).with_minimum(24.kib()))
+        .partition(Requirement::app("factory", AppType::Factory).with_extent(Extent::Fill));
+    assert!(solver.solve().is_ok());
+
+    let too_large = solver
+        .clone()
+        .partition(Requirement::data("storage", DataType::Fat).with_extent(Extent::Fixed(2.mib())));
+    assert!(matches!(
+        too_large.solve(),
+        Err(Error::UnsatisfiableLayout(reason)) if reason.contains("exceeds the 2MB flash")
+    ));
+
+    let no_size = solver
+        .clone()
+        .partition(Requirement::data("storage", DataType::Fat));
+    assert!(matches!(
+        no_size.solve(),
+        Err(Error::UnsatisfiableLayout(reason)) if reason.contains("'storage' has no size")
+    ));
+
+    let conflicting = solver
+        .partition(Requirement::data("phy_init", DataType::Phy).with_extent(Extent::Fixed(0x800)));
+    assert!(matches!(
+        conflicting.solve(),
+        Err(Error::UnsatisfiableLayout(reason)) if reason.contains("'phy_init'")
+    ));
+}
+
+#[test]
+fn test_rescale_partition_table() {
+    let csv = fs::read_to_string("tests/data/partitions-4MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let rescaled = table.rescale(FlashSize::_8Mb).unwrap();
+    let end = rescaled.partitions().iter().map(|p| p.end()).max();
+    assert_eq!(end, Some(0x800000));
+
+    for name in ["nvs", "otadata"] {
+        assert_eq!(rescaled.find(name), table.find(name));
+    }
+
+    let ota_0 = rescaled.find("ota_0").unwrap();
+    let ota_1 = rescaled.find("ota_1").unwrap();
+    assert!(ota_0.size() > 1408 * 1024);
+    assert_eq!(ota_0.size(), ota_1.size());
+    assert_eq!(ota_0.size() % 0x10000, 0);
+
+    // Rescaling back gives the original layout
+    let csv = fs::read_to_string("tests/data/partitions-16MB.csv").unwrap();
+    let expected = PartitionTable::try_from_str(csv).unwrap();
+    let csv = fs::read_to_string("tests/data/partitions-8MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let rescaled = table
+        .rescale_by(FlashSize::_16Mb, |p| p.name() == "user_fs")
+        .unwrap();
+    assert_eq!(rescaled, expected);
+    assert_eq!(
+        rescaled
+            .rescale_by(FlashSize::_8Mb, |p| p.name() == "user_fs")
+            .unwrap(),
+        table
+    );
+}
+
+#[test]
+fn test_rescale_from_context_flash_size() {
+    let csv = "nvs, data, nvs, 0x9000, 0x6000\nfactory, app, factory, 0x10000, 0x2F0000\n";
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    // Without a flash size in the context, the layout is taken to be for 4MB
+    let rescaled = table.rescale(FlashSize::_16Mb).unwrap();
+    assert_eq!(rescaled.find("factory").unwrap().size(), 0xEF0000);
+
+    // An 8MB layout keeps its 5MB of free space
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_8Mb);
+    let rescaled = table.rescale_with(&ctx, FlashSize::_16Mb).unwrap();
+    assert_eq!(rescaled.find("factory").unwrap().size(), 0xAF0000);
+
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_2Mb);
+    assert!(matches!(
+        table.rescale_with(&ctx, FlashSize::_16Mb),
+        Err(Error::UnsatisfiableLayout(reason)) if reason.contains("exceeds the 2MB flash")
+    ));
+}
+
+#[test]
+fn test_rescale_too_small() {
+    let csv = fs::read_to_string("tests/data/partitions-8MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    assert!(matches!(
+        table.rescale_by(FlashSize::_4Mb, |p| p.name() == "user_fs"),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+    assert!(matches!(
+        table.rescale(FlashSize::_1Mb),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+
+    // Size constraints are taken from the provided context; with 4kB wear
+    // levelling sectors the FAT partition cannot be made large enough
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota_2m.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    assert!(table.rescale(FlashSize::_2Mb).is_ok());
+    let ctx = ValidationContext::new().with_wl_sector_size(4096);
+    assert!(matches!(
+        table.rescale_with(&ctx, FlashSize::_2Mb),
+        Err(Error::UnsatisfiableLayout(..))
+    ));
+}
+
+#[test]
+fn test_compact_partition_table() {
+    let csv = fs::read_to_string("tests/data/partitions-4MB.csv").unwrap();
+    let mut table = PartitionTable::try_from_str(csv).unwrap();
+    table.remove("ota_1").unwrap();
+    table.resize("nvs", 0x4000).unwrap();
+
+    let (compacted, relocations) = table.compact().unwrap();
+    let offsets = compacted
+        .partitions()
+        .iter()
+        .map(|p| (p.name(), p.offset()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        offsets,
+        [
+            ("nvs".into(), 0x9000),
+            ("otadata".into(), 0xD000),
+            ("ota_0".into(), 0x10000),
+            ("uf2".into(), 0x170000),
+            ("user_fs".into(), 0x1B0000),
+        ]
+    );
+    assert_eq!(
+        relocations.into_iter().collect::<Vec<_>>(),
+        [
+            (0x9000, 0x9000),
+            (0xE000, 0xD000),
+            (0x10000, 0x10000),
+            (0x2D0000, 0x170000),
+            (0x310000, 0x1B0000),
+        ]
+    );
+
+    // Pinned partitions keep their offsets, and those following them are
+    // placed after them
+    let ctx = ValidationContext::default();
+    let (pinned, _) = table.compact_with(&ctx, |p| p.name() == "uf2").unwrap();
+    assert_eq!(pinned.find("uf2").unwrap().offset(), 0x2D0000);
+    assert_eq!(pinned.find("user_fs").unwrap().offset(), 0x310000);
+    assert_eq!(pinned.find("otadata").unwrap().offset(), 0xD000);
+
+    // Compacting a compact table changes nothing
+    let (again, relocations) = compacted.compact_with(&ctx, |_| false).unwrap();
+    assert!(relocations.iter().all(|(from, to)| from == to));
+    assert_eq!(again, compacted);
+
+    // Partitions are placed following the partition table at the offset given by
+    // the context
+    let table = PartitionTable::try_from_str(
+        "nvs,     data, nvs,     0x20000, 24K,\n\
+         factory, app,  factory, 0x30000, 1M,",
+    )
+    .unwrap();
+    let (compacted, _) = table.compact().unwrap();
+    assert_eq!(compacted.find("nvs").unwrap().offset(), 0x9000);
+    assert_eq!(compacted.find("factory").unwrap().offset(), 0x10000);
+
+    let ctx = ValidationContext::new().with_table_offset(0xA000);
+    let (compacted, _) = table.compact_with(&ctx, |_| false).unwrap();
+    assert_eq!(compacted.find("nvs").unwrap().offset(), 0xB000);
+    assert_eq!(compacted.find("factory").unwrap().offset(), 0x20000);
+}
+
+#[test]
+fn test_free_space_queries() {
+    let csv = fs::read_to_string("tests/data/partitions-4MB.csv").unwrap();
+    let mut table = PartitionTable::try_from_str(csv).unwrap();
+    table.remove("ota_1").unwrap();
+    table.resize("nvs", 0x4000).unwrap();
+
+    let ctx = ValidationContext::default().with_flash_size(FlashSize::_4Mb);
+    assert_eq!(
+        table.gaps_with(&ctx).collect::<Vec<_>>(),
+        [Region::new(0xD000, 0x1000), Region::new(0x170000, 0x160000)]
+    );
+    assert_eq!(
+        table.largest_gap_with(&ctx),
+        Some(Region::new(0x170000, 0x160000))
+    );
+
+    // Without a flash size, the free space extends to the 4GB limit
+    assert_eq!(
+        table.gaps().last(),
+        Some(Region::new(0x400000, 0xFFC0_0000))
+    );
+    assert_eq!(table.largest_gap(), table.gaps().last());
+
+    assert_eq!(
+        table.find_free_with(&ctx, 0x1000, DATA_PARTITION_ALIGNMENT),
+        Some(0xD000)
+    );
+    assert_eq!(
+        table.find_free_with(&ctx, 0x2000, DATA_PARTITION_ALIGNMENT),
+        Some(0x170000)
+    );
+    assert_eq!(
+        table.find_free_with(&ctx, 0x160000, Type::App.alignment()),
+        Some(0x170000)
+    );
+    assert_eq!(
+        table.find_free_with(&ctx, 0x170000, APP_PARTITION_ALIGNMENT),
+        None
+    );
+    assert_eq!(
+        table.find_free(0x170000, APP_PARTITION_ALIGNMENT),
+        Some(0x400000)
+    );
+
+    // The bootloader and partition table are never free
+    let table = PartitionTable::new(vec![Partition::new(
+        "factory",
+        Type::App,
+        SubType::App(AppType::Factory),
+        0x20000,
+        0x100000,
+        Flags::empty(),
+    )]);
+    let ctx = ctx.with_table_offset(0x10000);
+    assert_eq!(
+        table.gaps_with(&ctx).next(),
+        Some(Region::new(0x11000, 0xF000))
+    );
+}
+
+#[test]
+fn test_address_lookup() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let location = table.partition_at(0x12345).unwrap();
+    assert_eq!(location.partition().name(), "factory");
+    assert_eq!(location.offset(), 0x2345);
+
+    let location = table.partition_at(0x30FFFF).unwrap();
+    assert_eq!(location.partition().name(), "ota_1");
+    assert_eq!(location.offset(), 0xFFFFF);
+
+    assert!(table.partition_at(0x8000).is_none());
+    assert!(table.partition_at(0x310000).is_none());
+    assert!(table.partition_at(u32::MAX).is_none());
+
+    let index = table.index();
+    let locations = index
+        .partitions_in_range(0xE000..0x110001)
+        .into_iter()
+        .map(|l| (l.partition().name(), l.offset()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locations,
+        [
+            ("otadata".into(), 0x1000),
+            ("phy_init".into(), 0),
+            ("factory".into(), 0),
+            ("ota_0".into(), 0),
+        ]
+    );
+
+    assert!(index.partitions_in_range(0x0..0x9000).is_empty());
+    assert!(index.partitions_in_range(0x20000..0x20000).is_empty());
+
+    // Every address within a partition maps back to it
+    for partition in table.partitions() {
+        let last = partition.offset() + partition.size() - 1;
+        for address in [partition.offset(), last] {
+            assert_eq!(index.partition_at(address).unwrap().partition(), partition);
+        }
+    }
+}
+
+#[test]
+fn test_query_partitions() {
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let names = |query: Query| table.query(query).map(|p| p.name()).collect::<Vec<_>>();
+
+    assert_eq!(names(Query::new()).len(), table.partitions().len());
+    assert_eq!(
+        names(Query::new().with_type(Type::App)),
+        ["factory", "ota_0", "ota_1", "test"]
+    );
+    assert_eq!(
+        names(Query::new().with_subtype(DataType::Fat)),
+        ["flash_test"]
+    );
+    assert_eq!(
+        names(Query::new().with_type(Type::Data).with_label("nvs")),
+        ["nvs"]
+    );
+    assert!(names(Query::new().with_type(Type::App).with_label("nvs")).is_empty());
+
+    // Subtypes given numerically match, but named subtypes imply their type
+    assert_eq!(
+        names(Query::new().with_subtype(AppType::Factory)),
+        ["factory"]
+    );
+    assert_eq!(
+        names(Query::new().with_subtype(SubType::Custom(0x00))),
+        ["otadata", "factory"]
+    );
+    assert_eq!(
+        names(
+            Query::new()
+                .with_type(Type::Data)
+                .with_subtype(SubType::Custom(0x00))
+        ),
+        ["otadata"]
+    );
+}
+
+#[test]
+fn test_ota_layout() {
+    let csv = fs::read_to_string("tests/data/partition_table_unit_test_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    let ota = table.ota_layout().unwrap();
+
+    let slots = ota.slots().iter().map(|p| p.name()).collect::<Vec<_>>();
+    assert_eq!(slots, ["ota_0", "ota_1"]);
+    assert_eq!(ota.otadata().name(), "otadata");
+    assert_eq!(ota.factory().map(|p| p.name()), Some("factory".into()));
+    assert_eq!(ota.test().map(|p| p.name()), Some("test".into()));
+
+    let ota_0 = table.find("ota_0").unwrap();
+    let ota_1 = table.find("ota_1").unwrap();
+    assert_eq!(ota.next_slot(ota_0), ota_1);
+    assert_eq!(ota.next_slot(ota_1), ota_0);
+    assert_eq!(ota.next_slot(ota.factory().unwrap()), ota_0);
+    assert_eq!(ota.next_slot(ota.test().unwrap()), ota_0);
+}
+
+#[test]
+fn test_invalid_ota_layout() {
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert!(matches!(
+        table.ota_layout(),
+        Err(Error::InvalidOtaLayout(..))
+    ));
+
+    let layouts = [
+        // No otadata partition
+        "ota_0, app, ota_0, 0x10000, 1M,\n\
+         ota_1, app, ota_1, 0x110000, 1M,",
+        // Slots are not numbered consecutively
+        "otadata, data, ota, 0xd000, 0x2000,\n\
+         ota_0, app, ota_0, 0x10000, 1M,\n\
+         ota_2, app, ota_2, 0x110000, 1M,",
+        // A single slot cannot be updated
+        "otadata, data, ota, 0xd000, 0x2000,\n\
+         ota_0, app, ota_0, 0x10000, 1M,",
+        // Two partitions share a slot
+        "otadata, data, ota, 0xd000, 0x2000,\n\
+         ota_0, app, ota_0, 0x10000, 1M,\n\
+         ota_1, app, ota_1, 0x110000, 1M,\n\
+         ota_1b, app, ota_1, 0x210000, 1M,",
+    ];
+    for csv in layouts {
+        let table = PartitionTable::try_from_str(csv).unwrap();
+        assert!(matches!(
+            table.ota_layout(),
+            Err(Error::InvalidOtaLayout(..))
+        ));
+    }
+
+    // A single slot can be updated from the factory app
+    let table = PartitionTable::try_from_str(
+        "otadata, data, ota, 0xd000, 0x2000,\n\
+         factory, app, factory, 0x10000, 1M,\n\
+         ota_0, app, ota_0, 0x110000, 1M,",
+    )
+    .unwrap();
+    let ota = table.ota_layout().unwrap();
+    assert_eq!(ota.next_slot(ota.slots()[0]).name(), "ota_0");
+}
+
+#[test]
+fn test_diff_partition_tables() {
+    let csv = fs::read_to_string("tests/data/partitions-4MB.csv").unwrap();
+    let old = PartitionTable::try_from_str(csv).unwrap();
+    let csv = fs::read_to_string("tests/data/partitions-8MB.csv").unwrap();
+    let mut new = PartitionTable::try_from_str(csv).unwrap();
+
+    assert!(old.diff(&old).is_empty());
+    assert_eq!(old.diff(&old).to_string(), "No changes\n");
+
+    new.remove("uf2").unwrap();
+    new.set_flags("nvs", Flags::ENCRYPTED).unwrap();
+    new.insert(Partition::new(
+        "coredump",
+        Type::Data,
+        SubType::Data(DataType::Coredump),
+        0x410000,
+        0x10000,
+        Flags::empty(),
+    ))
+    .unwrap();
+
+    let diff = old.diff(&new);
+    assert_eq!(diff.added(), [new.find("coredump").unwrap().clone()]);
+    assert_eq!(diff.removed(), [old.find("uf2").unwrap().clone()]);
+
+    let changed = diff.changed();
+    assert_eq!(changed.len(), 4);
+    assert_eq!(changed[0].name(), "nvs");
+    assert_eq!(
+        changed[0].changes(),
+        [FieldChange::Flags {
+            old: Flags::empty(),
+            new: Flags::ENCRYPTED
+        }]
+    );
+    assert_eq!(changed[2].name(), "ota_1");
+    assert_eq!(
+        changed[2].changes(),
+        [
+            FieldChange::Offset {
+                old: 0x170000,
+                new: 0x210000
+            },
+            FieldChange::Size {
+                old: 0x160000,
+                new: 0x200000
+            }
+        ]
+    );
+
+    let text = diff.to_string();
+    assert!(text.contains("- uf2: app, factory at 0x2d0000 (0x40000 bytes)\n"));
+    assert!(text.contains("+ coredump: data, coredump at 0x410000 (0x10000 bytes)\n"));
+    assert!(text.contains("~ ota_1: offset 0x170000 -> 0x210000, size 0x160000 -> 0x200000\n"));
+
+    let json = diff.to_json().unwrap();
+    assert_eq!(
+        serde_json::from_str::<PartitionTableDiff>(&json).unwrap(),
+        diff
+    );
+}
+
+#[test]
+fn test_analyze_migration() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let old = PartitionTable::try_from_str(csv).unwrap();
+
+    let report = old.analyze_migration(&old, Some("ota_0")).unwrap();
+    assert!(report.is_safe());
+
+    let new = PartitionTable::try_from_str(
+        "nvs, data, nvs, 0x9000, 0x4000,\n\
+         otadata, data, ota, 0xd000, 0x2000,\n\
+         factory, app, factory, 0x10000, 0xC0000,\n\
+         ota_0, app, ota_0, 0xD0000, 1M,\n\
+         ota_1, app, ota_1, 0x210000, 1M,\n\
+         storage, data, fat, 0x310000, 1M,",
+    )
+    .unwrap();
+
+    let report = old.analyze_migration(&new, Some("ota_1")).unwrap();
+    let statuses = report
+        .partitions()
+        .iter()
+        .map(|p| (p.before().name(), p.status()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            ("nvs".into(), MigrationStatus::Preserved),
+            ("otadata".into(), MigrationStatus::Preserved),
+            ("phy_init".into(), MigrationStatus::Destroyed),
+            ("factory".into(), MigrationStatus::Truncated),
+            ("ota_0".into(), MigrationStatus::Relocated),
+            ("ota_1".into(), MigrationStatus::Preserved),
+        ]
+    );
+    assert!(!report.running_slot_clobbered());
+    assert!(!report.otadata_clobbered());
+    assert!(!report.is_safe());
+    assert_eq!(
+        report.partition("ota_0").and_then(|p| p.after()),
+        new.find("ota_0")
+    );
+    assert!(
+        report
+            .to_string()
+            .contains("ota_0: relocated (0x110000 -> 0xd0000)\n")
+    );
+
+    let report = old.analyze_migration(&new, Some("ota_0")).unwrap();
+    assert!(report.running_slot_clobbered());
+
+    // Moving otadata loses the boot selection
+    let mut moved = old.clone();
+    moved.remove("phy_init").unwrap();
+    moved.move_to("otadata", 0xE000).unwrap();
+    let report = old.analyze_migration(&moved, None).unwrap();
+    assert!(report.otadata_clobbered());
+    assert!(!report.running_slot_clobbered());
+
+    assert!(matches!(
+        old.analyze_migration(&new, Some("ota_7")),
+        Err(Error::PartitionNotFound(..))
+    ));
+}
+
+#[test]
+fn test_analyze_migration_by_byte_range() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let old = PartitionTable::try_from_str(csv).unwrap();
+
+    // A partition which both moves and shrinks is reported as such
+    let mut moved = old.clone();
+    moved.resize("ota_1", 0x80000).unwrap();
+    moved.move_to("ota_1", 0x310000).unwrap();
+    let report = old.analyze_migration(&moved, None).unwrap();
+    let ota_1 = report.partition("ota_1").unwrap();
+    assert_eq!(ota_1.status(), MigrationStatus::RelocatedAndTruncated);
+    assert!(ota_1.status().is_relocated() && ota_1.status().is_truncated());
+    assert!(report.to_string().contains(
+        "ota_1: relocated_and_truncated (0x210000 -> 0x310000, 0x100000 -> 0x80000 bytes)\n"
+    ));
+
+    // Renaming a partition in place does not clobber its contents
+    let mut renamed = old.clone();
+    renamed.rename("ota_0", "app0").unwrap();
+    renamed.rename("otadata", "ota_data").unwrap();
+    let report = old.analyze_migration(&renamed, Some("ota_0")).unwrap();
+    assert!(report.is_safe());
+    assert!(!report.running_slot_clobbered());
+    assert!(!report.otadata_clobbered());
+
+    // A different partition overwriting the running slot clobbers it, even if a
+    // partition of the same name survives elsewhere
+    let new = PartitionTable::try_from_str(
+        "nvs, data, nvs, 0x9000, 0x4000,\n\
+         otadata, data, ota, 0xd000, 0x2000,\n\
+         factory, app, factory, 0x10000, 1M,\n\
+         storage, data, fat, 0x110000, 1M,\n\
+         ota_0, app, ota_0, 0x210000, 1M,\n\
+         ota_1, app, ota_1, 0x310000, 1M,",
+    )
+    .unwrap();
+    let report = old.analyze_migration(&new, Some("ota_0")).unwrap();
+    assert!(report.running_slot_clobbered());
+
+    // As does any partition overlapping otadata, even though it is kept in place
+    let mut partitions = old.partitions().clone();
+    partitions.push(Partition::new(
+        "scratch",
+        Type::Data,
+        SubType::Data(DataType::Undefined),
+        0xE000,
+        0x1000,
+        Flags::empty(),
+    ));
+    let overlapping = PartitionTable::new(partitions);
+    let report = old.analyze_migration(&overlapping, Some("ota_0")).unwrap();
+    assert!(report.otadata_clobbered());
+    assert!(!report.running_slot_clobbered());
+}
+
+#[test]
+fn test_analyze_migration_of_renamed_partitions() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let old = PartitionTable::try_from_str(csv).unwrap();
+
+    // A partition renamed in place is matched by its offset, type and subtype
+    let mut renamed = old.clone();
+    renamed.rename("ota_0", "app0").unwrap();
+    renamed.resize("app0", 0x80000).unwrap();
+    let report = old.analyze_migration(&renamed, None).unwrap();
+    let ota_0 = report.partition("ota_0").unwrap();
+    assert_eq!(ota_0.status(), MigrationStatus::Truncated);
+    assert_eq!(ota_0.after().unwrap().name(), "app0");
+    assert!(
+        report
+            .to_string()
+            .contains("ota_0: truncated ('ota_0' -> 'app0', 0x100000 -> 0x80000 bytes)\n")
+    );
+
+    // Unless its subtype changes
+    let mut partitions = old.partitions().clone();
+    partitions[4] = Partition::new(
+        "app0",
+        Type::App,
+        SubType::App(AppType::Ota_2),
+        0x110000,
+        0x100000,
+        Flags::empty(),
+    );
+    let retyped = PartitionTable::new(partitions);
+    let report = old.analyze_migration(&retyped, None).unwrap();
+    let ota_0 = report.partition("ota_0").unwrap();
+    assert_eq!(ota_0.status(), MigrationStatus::Destroyed);
+    assert!(ota_0.after().is_none());
+
+    // Or its new name is that of another partition of the old partition table
+    let mut taken = old.clone();
+    taken.remove("factory").unwrap();
+    taken.rename("ota_0", "factory").unwrap();
+    let report = old.analyze_migration(&taken, None).unwrap();
+    assert_eq!(
+        report.partition("ota_0").unwrap().status(),
+        MigrationStatus::Destroyed
+    );
+    assert_eq!(
+        report.partition("factory").unwrap().status(),
+        MigrationStatus::Destroyed
+    );
+}
+
+#[test]
+fn test_apply_overlays() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let base = PartitionTable::try_from_str(csv).unwrap();
+
+    let csv = fs::read_to_string("tests/data/overlay_16MB.csv").unwrap();
+    let large = Overlay::try_from_str("16MB", csv).unwrap();
+    let csv = fs::read_to_string("tests/data/overlay_zigbee.csv").unwrap();
+    let zigbee = Overlay::try_from_str("zigbee", csv).unwrap();
+
+    let ctx = ValidationContext::default().with_flash_size(FlashSize::_16Mb);
+    let merged = base.apply_overlays_with([&large, &zigbee], &ctx).unwrap();
+
+    let layout = merged
+        .table()
+        .partitions()
+        .iter()
+        .map(|p| (p.name(), p.offset(), p.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        layout,
+        [
+            ("nvs".into(), 0x9000, 0x4000),
+            ("otadata".into(), 0xD000, 0x2000),
+            ("factory".into(), 0x10000, 0x100000),
+            ("ota_0".into(), 0x110000, 0x200000),
+            ("ota_1".into(), 0x310000, 0x100000),
+            ("storage".into(), 0x410000, 0x400000),
+            ("zb_storage".into(), 0x810000, 0x4000),
+        ]
+    );
+
+    assert_eq!(merged.origin("nvs"), None);
+    assert_eq!(merged.origin("ota_1"), None);
+    assert_eq!(merged.origin("ota_0"), Some("16MB"));
+    assert_eq!(merged.origin("storage"), Some("16MB"));
+    assert_eq!(merged.origin("zb_storage"), Some("zigbee"));
+
+    let storage = merged.table().find("storage").unwrap();
+    assert_eq!(storage.subtype(), SubType::Data(DataType::Littlefs));
+
+    // Overlays can also be built in code
+    let overlay = Overlay::new("pinned")
+        .resize("nvs", 0x3000)
+        .insert(Partition::new(
+            "coredump",
+            Type::Data,
+            SubType::Data(DataType::Coredump),
+            0x310000,
+            0x10000,
+            Flags::empty(),
+        ));
+    let merged = base.apply_overlay(&overlay).unwrap();
+    assert_eq!(merged.table().find("nvs").unwrap().size(), 0x3000);
+    assert_eq!(merged.table().find("coredump").unwrap().offset(), 0x310000);
+    assert_eq!(
+        merged.into_table().partitions().last().unwrap().name(),
+        "coredump"
+    );
+}
+
+#[test]
+fn test_invalid_overlays() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let base = PartitionTable::try_from_str(csv).unwrap();
+
+    let overlay = Overlay::new("missing").remove("zb_storage");
+    assert!(matches!(
+        base.apply_overlay(&overlay),
+        Err(Error::PartitionNotFound(name)) if name == "zb_storage"
+    ));
+
+    let overlay = Overlay::try_from_str("incomplete", "storage, data, , , 1M,").unwrap();
+    assert!(matches!(
+        base.apply_overlay(&overlay),
+        Err(Error::InvalidOverlay(..))
+    ));
+
+    // Growing a partition moves those following it, but the merged table is still
+    // validated
+    let overlay = Overlay::try_from_str("grown", "nvs, , , , 0x5000,").unwrap();
+    let merged = base.apply_overlay(&overlay).unwrap();
+    assert_eq!(merged.table().find("otadata").unwrap().offset(), 0xE000);
+
+    let overlay = Overlay::try_from_str("overlapping", "otadata, , , 0xA000, ,").unwrap();
+    assert!(matches!(
+        base.apply_overlay(&overlay),
+        Err(Error::OverlappingPartitions(..))
+    ));
+}
+
+#[test]
+fn test_render_template() {
+    let source = fs::read_to_string("tests/data/template.csv").unwrap();
+    let template = Template::new(source).with_variables([
+        ("NVS_SIZE", "24K"),
+        ("APP_SIZE", "0x180000"),
+        ("FLASH_SIZE", "4M"),
+        ("CONFIG_ZIGBEE_ENABLED", "n"),
+    ]);
+
+    let csv = template.render().unwrap();
+    assert!(!csv.contains("zb_"));
+    assert!(!csv.contains("#if"));
+    assert!(csv.contains("ota_0,    app,  ota_0,    ,       0x180000,"));
+
+    let table = template.to_table().unwrap();
+    assert_eq!(table, PartitionTable::try_from_str(csv).unwrap());
+    assert_eq!(table.find("storage").unwrap().size(), 0xE0000);
+
+    let table = template
+        .clone()
+        .with_variable("CONFIG_ZIGBEE_ENABLED", "y")
+        .to_table()
+        .unwrap();
+    assert_eq!(table.find("zb_fct").unwrap().size(), 0x4000);
+    assert_eq!(table.find("zb_storage").unwrap().size(), 0x10000);
+
+    let table = template
+        .with_feature("CONFIG_ZIGBEE_ENABLED")
+        .with_feature("CONFIG_ZB_SMALL_STORAGE")
+        .to_table()
+        .unwrap();
+    assert!(table.find("zb_fct").is_some());
+    assert!(table.find("zb_storage").is_none());
+}
+
+#[test]
+fn test_template_errors_point_to_template_line() {
+    let source = fs::read_to_string("tests/data/template.csv").unwrap();
+    let template = Template::new(source)
+        .with_variable("NVS_SIZE", "24K")
+        .with_variable("APP_SIZE", "1M");
+
+    // FLASH_SIZE is undefined
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 13, .. })
+    ));
+
+    let template = template
+        .with_variable("FLASH_SIZE", "4M")
+        .with_variable("APP_SIZE", "1M / 0");
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 5, .. })
+    ));
+
+    let template = Template::new(
+        "nvs, data, nvs, , 24K,\n\
+         #if FOO\n\
+         \n\
+         #endif\n\
+         factory, app, bogus, , 1M,",
+    );
+    assert!(matches!(
+        template.to_table(),
+        Err(Error::TemplateError { line: 5, .. })
+    ));
+
+    let template = Template::new("#if FOO\nnvs, data, nvs, , 24K,");
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 1, .. })
+    ));
+
+    let template = Template::new("#ifdef FOO\nnvs, data, nvs, , 24K,");
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 1, .. })
+    ));
+
+    // Validation errors point to the offending partition's row
+    let template = Template::new(
+        "# Name, Type, SubType, Offset, Size\n\
+         nvs,     data, nvs,     0x9000,  24K,\n\
+         \n\
+         factory, app,  factory, 0x10000, ${APP_SIZE},\n\
+         storage, data, fat,     0x100000, 64K,",
+    )
+    .with_variable("APP_SIZE", "1M");
+    assert!(matches!(
+        template.to_table(),
+        Err(Error::TemplateError { line: 5, .. })
+    ));
+
+    let template = Template::new(
+        "nvs,     data, nvs,     0x9000,  24K,\n\
+         factory, app,  factory, 0x10000, 1M,\n\
+         nvs,     data, nvs,     0x200000, 24K,",
+    );
+    assert!(matches!(
+        template.to_table(),
+        Err(Error::TemplateError { line: 3, .. })
+    ));
+
+    let template = Template::new(
+        "nvs,     data, nvs,     0x9000,  24K,\n\
+         factory, app,  factory, 0x18000, 1M,",
+    );
+    assert!(matches!(
+        template.to_table(),
+        Err(Error::TemplateError { line: 2, .. })
+    ));
+
+    let template = Template::new(
+        "nvs,     data, nvs,     0x9000,  24K,\n\
+         factory, app,  factory, 0x10000, 1M,\n\
+         a_very_long_partition_name, data, fat, , 64K,",
+    );
+    assert!(matches!(
+        template.to_table(),
+        Err(Error::TemplateError { line: 3, .. })
+    ));
+
+    // Errors which concern the table as a whole are returned unchanged
+    let template = Template::new("nvs, data, nvs, 0x9000, 24K,");
+    assert!(matches!(template.to_table(), Err(Error::NoAppPartition)));
+}
+
+#[test]
+fn test_template_comments_and_directives() {
+    let template = Template::new(
+        "# if this is only a comment, costing ${NOTHING}\n\
+         #ifconfig is not a directive either\n\
+         nvs,     data, nvs,     , 24K,\n\
+         factory, app,  factory, , 1M,\n\
+         #if ZIGBEE\n\
+         zb_fct,  data, fat,     , 16K,\n\
+         #endif",
+    );
+
+    let csv = template.render().unwrap();
+    assert!(csv.starts_with(
+        "# if this is only a comment, costing ${NOTHING}\n#ifconfig is not a directive either\n"
+    ));
+    assert_eq!(template.to_table().unwrap().partitions().len(), 2);
+    assert_eq!(
+        template
+            .with_feature("ZIGBEE")
+            .to_table()
+            .unwrap()
+            .partitions()
+            .len(),
+        3
+    );
+
+    // A comment which looks like a directive, but is not a valid one, is an error
+    // rather than being silently passed through
+    let template = Template::new(
+        "nvs,     data, nvs,     , 24K,\n\
+         #if also a comment\n\
+         factory, app,  factory, , 1M,\n\
+         #endif",
+    );
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 2, .. })
+    ));
+
+    let template = Template::new("#endif // ZIGBEE");
+    assert!(matches!(
+        template.render(),
+        Err(Error::TemplateError { line: 1, .. })
+    ));
+}
+
+#[test]
+fn test_template_respects_csv_quoting() {
+    let template = Template::new(
+        "nvs,          data, nvs,     , 24K,\n\
+         \"fac,tory\",    app,  factory, , 512K * 2,",
+    );
+
+    let csv = template.render().unwrap();
+    assert!(csv.contains("\"fac,tory\""));
+    assert!(csv.contains("0x100000"));
+
+    // Quoted expressions are evaluated as well
+    let template = Template::new(
+        "nvs,     data, nvs,     , 24K,\n\
+         factory, app,  factory, ,\"1M + 1M\",",
+    );
+    let table = template.to_table().unwrap();
+    assert_eq!(table.find("factory").unwrap().size(), 0x200000);
+}
+
+#[test]
+fn test_presets() {
+    use esp_idf_part::presets::{self, Preset};
+
+    let table = presets::two_ota(0x8000).unwrap();
+    let expected = PartitionTable::try_from_str(
+        fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap(),
+    )
+    .unwrap();
+    assert_eq!(table, expected);
+
+    // Presets are placed following the partition table
+    let table = presets::singleapp(0xC000).unwrap();
+    assert_eq!(table.find("nvs").unwrap().offset(), 0xD000);
+    assert_eq!(table.find("factory").unwrap().offset(), 0x20000);
+
+    assert_eq!(
+        Preset::from_kconfig("CONFIG_PARTITION_TABLE_TWO_OTA_ENCRYPTED_NVS"),
+        Some(Preset::TwoOtaEncryptedNvs)
+    );
+    assert_eq!(
+        Preset::from_kconfig("PARTITION_TABLE_SINGLE_APP_LARGE"),
+        Some(Preset::SingleAppLarge)
+    );
+    assert_eq!(Preset::from_kconfig("CONFIG_PARTITION_TABLE_CUSTOM"), None);
+    assert_eq!(Preset::TwoOtaLarge.coredump(), None);
+
+    for preset in [
+        Preset::SingleApp,
+        Preset::SingleAppCoredump,
+        Preset::SingleAppEncryptedNvs,
+        Preset::SingleAppLarge,
+        Preset::SingleAppLargeCoredump,
+        Preset::SingleAppLargeEncryptedNvs,
+        Preset::TwoOta,
+        Preset::TwoOtaCoredump,
+        Preset::TwoOtaEncryptedNvs,
+        Preset::TwoOtaLarge,
+    ] {
+        let table = preset.table(0x8000).unwrap();
+        let ctx = ValidationContext::new().with_flash_size(FlashSize::_4Mb);
+        assert!(table.validate_with(&ctx).is_ok(), "{}", preset.filename());
+    }
+}
+
+#[cfg(feature = "catalog")]
+#[test]
+fn test_identify_catalog_scheme() {
+    use esp_idf_part::catalog::{self, Framework};
+
+    for scheme in catalog::schemes() {
+        let table = scheme.table().unwrap();
+        let ctx = ValidationContext::new().with_flash_size(scheme.flash_size());
+        assert!(table.validate_with(&ctx).is_ok(), "{scheme}");
+        assert_eq!(catalog::identify(&table), Some(*scheme));
+    }
+
+    let csv = fs::read_to_string("tests/data/partitions-8MB-no-uf2.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    let scheme = catalog::identify(&table).unwrap();
+    assert_eq!(scheme.framework(), Framework::CircuitPython);
+    assert_eq!(scheme.name(), "partitions-8MB-no-uf2");
+    assert_eq!(scheme.flash_size(), FlashSize::_8Mb);
+
+    // ESPHome leaves the offsets to be assigned, placing phy_init after otadata
+    let csv = fs::read_to_string("tests/data/esphome_idf_4MB.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert_eq!(table.find("phy_init").unwrap().offset(), 0xb000);
+    let scheme = catalog::identify(&table).unwrap();
+    assert_eq!(scheme.framework(), Framework::Esphome);
+    assert_eq!(scheme.name(), "default");
+
+    let csv = fs::read_to_string("tests/data/esp32_partition_app2880k_fs320k.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert_eq!(table.find("spiffs").unwrap().size(), 320 * 1024);
+    let scheme = catalog::identify(&table).unwrap();
+    assert_eq!(scheme.framework(), Framework::Tasmota);
+    assert_eq!(scheme.name(), "safeboot");
+
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    assert_eq!(catalog::identify(&table), None);
+}
+
+#[test]
+fn test_partition_metadata() {
+    let csv = fs::read_to_string("tests/data/metadata.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let nvs = table.find("nvs").unwrap();
+    assert_eq!(
+        nvs.metadata().get("desc").map(String::as_str),
+        Some("Wi-Fi credentials and calibration data")
+    );
+    assert_eq!(
+        nvs.metadata().get("owner").map(String::as_str),
+        Some("connectivity")
+    );
+    assert!(table.find("phy_init").unwrap().metadata().is_empty());
+
+    let storage = table.find("storage").unwrap();
+    assert_eq!(
+        storage.metadata().get("desc").map(String::as_str),
+        Some("Web assets served over HTTP")
+    );
+    assert_eq!(
+        storage.metadata().get("filesystem").map(String::as_str),
+        Some("littlefs")
+    );
+    assert_eq!(
+        storage.metadata().get("source").map(String::as_str),
+        Some("./data")
+    );
+
+    let metadata = |table: &PartitionTable| {
+        table
+            .partitions()
+            .iter()
+            .map(|p| p.metadata().clone())
+            .collect::<Vec<_>>()
+    };
+
+    // CSV and JSON preserve metadata
+    let csv = table.to_csv().unwrap();
+    assert!(csv.contains("# @desc Main application\nfactory,"));
+    let from_csv = PartitionTable::try_from_str(csv).unwrap();
+    assert_eq!(from_csv, table);
+    assert_eq!(metadata(&from_csv), metadata(&table));
+
+    let json = serde_json::to_string(&table).unwrap();
+    let from_json = serde_json::from_str::<PartitionTable>(&json).unwrap();
+    assert_eq!(from_json, table);
+    assert_eq!(metadata(&from_json), metadata(&table));
+
+    // The binary format has no room for metadata, which does not affect equality
+    let bin = PartitionTable::try_from_bytes(table.to_bin().unwrap()).unwrap();
+    assert!(bin.partitions().iter().all(|p| p.metadata().is_empty()));
+    assert_eq!(bin, table);
+    assert!(table.is_normalized());
+
+    let partition = Partition::new(
+        "ota_0",
+        Type::App,
+        SubType::App(AppType::Ota_0),
+        0x10000,
+        0x100000,
+        Flags::empty(),
+    )
+    .with_metadata("desc", "first slot")
+    .unwrap();
+    assert_eq!(partition.metadata().len(), 1);
+
+    // Entries which cannot be written as CSV annotations are rejected
+    for (key, value) in [
+        ("", "empty key"),
+        ("two words", "value"),
+        ("key=value", "value"),
+        ("desc", "first\nsecond"),
+        ("desc", " padded "),
+    ] {
+        assert!(matches!(
+            partition.clone().with_metadata(key, value),
+            Err(Error::InvalidMetadata { .. })
+        ));
+    }
+
+    // ...including when deserialized, where they could otherwise inject rows into
+    // the CSV
+    let json = serde_json::to_string(&table).unwrap();
+    assert!(json.contains(r#""Main application""#));
+    let json = json.replace(
+        r#""Main application""#,
+        r#""x\nevil, app, ota_0, 0x200000, 1M,""#,
+    );
+    assert!(serde_json::from_str::<PartitionTable>(&json).is_err());
+}
+
+#[test]
+fn test_partition_metadata_is_carried_through() {
+    let csv = fs::read_to_string("tests/data/metadata.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+    let desc = |table: &PartitionTable, name: &str| {
+        table
+            .find(name)
+            .and_then(|p| p.metadata().get("desc").cloned())
+    };
+
+    // Overlays modify partitions in place, keeping their metadata
+    let overlay = Overlay::new("bigger").resize("storage", 1024 * 1024);
+    let overlaid = table.apply_overlay(&overlay).unwrap().into_table();
+    assert_eq!(overlaid.find("storage").unwrap().size(), 0x100000);
+    assert_eq!(
+        desc(&overlaid, "storage").as_deref(),
+        Some("Web assets served over HTTP")
+    );
+
+    let overlay = Overlay::try_from_str(
+        "owner",
+        "# @owner web\n\
+         storage, , , , 1M,\n\
+         # @desc Extra storage\n\
+         extra, data, fat, , 64K,",
+    )
+    .unwrap();
+    let overlaid = table.apply_overlay(&overlay).unwrap().into_table();
+    let storage = overlaid.find("storage").unwrap();
+    assert_eq!(
+        storage.metadata().get("owner").map(String::as_str),
+        Some("web")
+    );
+    assert_eq!(
+        desc(&overlaid, "storage").as_deref(),
+        Some("Web assets served over HTTP")
+    );
+    assert_eq!(desc(&overlaid, "extra").as_deref(), Some("Extra storage"));
+
+    // So does the builder
+    let factory = table.find("factory").unwrap().clone();
+    let built = PartitionTableBuilder::new()
+        .partition(factory)
+        .build()
+        .unwrap();
+    assert_eq!(desc(&built, "factory").as_deref(), Some("Main application"));
+}
+
+#[test]
+fn test_display_table_and_flash_map() {
+    let csv = fs::read_to_string("tests/data/single_factory_no_ota.csv").unwrap();
+    let table = PartitionTable::try_from_str(csv).unwrap();
+
+    let display = table.to_string();
+    assert_eq!(
+        display,
+        "# ESP-IDF Partition Table\n\
+         # Name,   Type, SubType, Offset,  Size, Flags\n\
+         nvs,      data, nvs,     0x9000,  24K,\n\
+         phy_init, data, phy,     0xf000,  4K,\n\
+         factory,  app,  factory, 0x10000, 1M,\n"
+    );
+    assert_eq!(PartitionTable::try_from_str(display).unwrap(), table);
+
+    let partition = Partition::new(
+        "nvs_key",
+        Type::Data,
+        SubType::Data(DataType::NvsKeys),
+        0x20000,
+        0x1000,
+        Flags::ENCRYPTED,
+    );
+    assert_eq!(
+        partition.to_string(),
+        "nvs_key, data, nvs_keys, 0x20000, 4K, encrypted"
+    );
+
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_4Mb);
+    let map = table.flash_map_with(&ctx).with_width(16).to_string();
+    assert_eq!(
+        map,
+        "Flash map (4MB)\n\
+         0x000000  <bootloader>         32K  |=               |\n\
+         0x008000  <partition table>     4K  |=               |\n\
+         0x009000  nvs                  24K  |#               |\n\
+         0x00f000  phy_init              4K  |#               |\n\
+         0x010000  factory               1M  |#####           |\n\
+         0x110000  <free>             3008K  |    ............|\n"
+    );
+}
+
+#[test]
+fn test_visualize_partition_table() {
+    let csv = fs::read_to_string("tests/data/factory_app_two_ota.csv").unwrap();
+    let before = PartitionTable::try_from_str(csv).unwrap();
+
+    let svg = before.to_svg();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.ends_with("</svg>\n"));
+    assert!(svg.contains("Partition table (4MB)"));
+    assert!(svg.contains("0x110000  ota_0  1M"));
+    assert!(svg.contains("&lt;partition table&gt;"));
+    assert!(svg.contains("&lt;free&gt;"));
+
+    // OTA slots share a colour and a legend entry
+    assert!(svg.contains(">app, factory</text>"));
+    assert_eq!(svg.matches(">app, ota</text>").count(), 1);
+
+    // As do custom types
+    let mut partitions = before.partitions().clone();
+    for (name, ty, offset) in [("custom_a", 0x40, 0x310000), ("custom_b", 0x41, 0x320000)] {
+        partitions.push(Partition::new(
+            name,
+            Type::Custom(ty),
+            SubType::Custom(0x00),
+            offset,
+            0x10000,
+            Flags::empty(),
+        ));
+    }
+    let svg = PartitionTable::new(partitions).to_svg();
+    assert_eq!(svg.matches(">custom</text>").count(), 1);
+    assert_eq!(svg.matches("#8cd17d").count(), 3);
+
+    let mut after = before.clone();
+    after.resize("ota_1", 0x200000).unwrap();
+    after
+        .insert(Partition::new(
+            "a&b",
+            Type::Data,
+            SubType::Data(DataType::Fat),
+            0x410000,
+            0x10000,
+            Flags::empty(),
+        ))
+        .unwrap();
+
+    let ctx = ValidationContext::new().with_flash_size(FlashSize::_8Mb);
+    let visualization = Visualization::new(&before)
+        .with_context(&ctx)
+        .with_title("Before")
+        .with_comparison("After", &after);
+
+    let svg = visualization.to_svg();
+    assert!(svg.contains("Before (8MB)"));
+    assert!(svg.contains("After (8MB)"));
+    assert!(svg.contains("a&amp;b"));
+    assert!(!svg.contains("a&b"));
+
+    let html = visualization.to_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>Before / After</title>"));
+    assert!(html.contains(&svg));
+}